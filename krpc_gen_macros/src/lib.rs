@@ -0,0 +1,170 @@
+//! Generate kRPC bindings inline instead of from a build script:
+//!
+//! ```ignore
+//! mod schema;
+//! krpc_gen_macros::krpc_service!("schemas/KRPC.SpaceCenter.json", services = ["SpaceCenter"]);
+//! ```
+//!
+//! expands to `pub mod space_center { ... }` with the same code the
+//! generator would write to `space_center.rs`, so the invocation takes the
+//! place of the `mod` declarations of the generated files. The code is built
+//! by the generator's tokens backend.
+
+use std::path::PathBuf;
+use convert_case::{Case, Casing};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{bracketed, Ident, LitBool, LitStr, Token};
+
+/// `krpc_service!("path/to/schema.json", services = ["SpaceCenter"], procedure_ids = true, config = "krpc-gen.toml")`
+///
+/// The paths are relative to the crate's `Cargo.toml`. The schema path can be
+/// a schema file or a directory of them. Without `services` every service is
+/// generated. `include = ["SpaceCenter.Vessel_get_*"]` and `exclude = [...]`
+/// take the same patterns as `--include` and `--exclude`.
+#[proc_macro]
+pub fn krpc_service(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let arguments = syn::parse_macro_input!(input as Arguments);
+    expand(arguments).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+struct Arguments {
+    path: LitStr,
+    services: Vec<LitStr>,
+    procedure_ids: bool,
+    config: Option<LitStr>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Parse for Arguments {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut arguments = Arguments {
+            path: input.parse()?,
+            services: Vec::new(),
+            procedure_ids: false,
+            config: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "services" => arguments.services = parse_strings(input)?,
+                "include" => arguments.include = parse_strings(input)?.iter().map(LitStr::value).collect(),
+                "exclude" => arguments.exclude = parse_strings(input)?.iter().map(LitStr::value).collect(),
+                "procedure_ids" => arguments.procedure_ids = input.parse::<LitBool>()?.value,
+                "config" => arguments.config = Some(input.parse()?),
+                _ => return Err(syn::Error::new(key.span(), format!("unknown argument `{}`, expected `services`, `procedure_ids`, `config`, `include` or `exclude`", key))),
+            }
+        }
+        Ok(arguments)
+    }
+}
+
+/// `["a", "b"]`
+fn parse_strings(input: ParseStream) -> syn::Result<Vec<LitStr>> {
+    let content;
+    bracketed!(content in input);
+    let strings = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+    Ok(strings.into_iter().collect())
+}
+
+fn expand(arguments: Arguments) -> syn::Result<TokenStream> {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let path = manifest_dir.join(arguments.path.value());
+    let config = arguments.config.as_ref().map(|config| manifest_dir.join(config.value()));
+
+    let mut builder = krpc_gen::Builder::new()
+        .input(&path)
+        .options(krpc_gen::Options {
+            procedure_ids: arguments.procedure_ids,
+            config: config.clone(),
+            include: arguments.include.clone(),
+            exclude: arguments.exclude.clone(),
+            ..krpc_gen::Options::default()
+        })
+        .emit_rerun_if_changed(false)
+        .emit_warnings(false);
+    for service in &arguments.services {
+        builder = builder.service(service.value());
+    }
+    let rendered = builder.render_tokens().map_err(|e| to_syn_error(&arguments, &e))?;
+
+    let modules = rendered.into_iter().map(|(service_name, tokens)| {
+        let module = format_ident!("{}", service_name.to_case(Case::Snake));
+        quote! {
+            pub mod #module {
+                #tokens
+            }
+        }
+    });
+
+    // Recompile when the schema or the configuration changes
+    let tracked = path.is_file().then_some(&path).into_iter().chain(&config).map(|path| {
+        let path = path.to_string_lossy();
+        quote! { const _: &[u8] = include_bytes!(#path); }
+    });
+
+    Ok(quote! {
+        #(#tracked)*
+        #(#modules)*
+    })
+}
+
+/// Point unknown services at their entry in `services`, invalid configuration
+/// at `config` and anything else at the schema path
+fn to_syn_error(arguments: &Arguments, error: &krpc_gen::Error) -> syn::Error {
+    let span = match error {
+        krpc_gen::Error::ServiceNotFound(name) => arguments.services.iter()
+            .find(|service| &service.value() == name)
+            .map_or(arguments.path.span(), |service| service.span()),
+        krpc_gen::Error::Config(..) => arguments.config.as_ref().map_or(arguments.path.span(), |config| config.span()),
+        _ => arguments.path.span(),
+    };
+    syn::Error::new(span, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+        "SpaceCenter": {"id": 2, "procedures": {"get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}}}, "classes": {}, "enumerations": {}}
+    }"#;
+
+    #[test]
+    fn test_expand() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_macros_test_expand_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("schema.json");
+        std::fs::write(&input, SCHEMA).unwrap();
+        let path = input.to_string_lossy();
+
+        let arguments: Arguments = syn::parse_quote!(#path, services = ["SpaceCenter"]);
+        let expanded = expand(arguments).unwrap().to_string();
+        assert!(expanded.contains("pub mod space_center"));
+        assert!(expanded.contains("pub async fn get_ut"));
+
+        let arguments: Arguments = syn::parse_quote!(#path, exclude = ["SpaceCenter.get_*"]);
+        let expanded = expand(arguments).unwrap().to_string();
+        assert!(expanded.contains("pub mod space_center"));
+        assert!(!expanded.contains("get_ut"));
+
+        let arguments: Arguments = syn::parse_quote!(#path, services = ["Drawing"]);
+        let error = expand(arguments).unwrap_err();
+        assert_eq!(error.to_string(), "service Drawing not found in the schema");
+
+        let arguments: Arguments = syn::parse_quote!("missing.json");
+        assert!(expand(arguments).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
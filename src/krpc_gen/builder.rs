@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use proc_macro2::TokenStream;
+
+use crate::check::{self, Mismatch};
+use crate::config::Config;
+use crate::error::Error;
+use crate::filter;
+use crate::options::{Layout, Options};
+use crate::original;
+use crate::output;
+use crate::parser;
+use crate::tokens;
+use crate::writer;
+
+/// Generates bindings from a build script, one file per service:
+///
+/// ```no_run
+/// // in build.rs
+/// krpc_gen::Builder::new()
+///     .input("schemas/KRPC.SpaceCenter.json")
+///     .service("SpaceCenter")
+///     .generate()
+///     .unwrap();
+/// ```
+///
+/// writes `$OUT_DIR/space_center.rs`, which the crate then includes with
+/// `include!(concat!(env!("OUT_DIR"), "/space_center.rs"));`. With
+/// `Layout::Modules` it writes `$OUT_DIR/space_center/mod.rs` and a file per
+/// class next to it instead.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    inputs: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    services: Vec<String>,
+    server: bool,
+    options: Options,
+    rerun_if_changed: bool,
+    warnings: bool,
+    managed: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder {
+            inputs: Vec::new(),
+            out_dir: None,
+            services: Vec::new(),
+            server: false,
+            options: Options::default(),
+            rerun_if_changed: true,
+            warnings: true,
+            managed: false,
+        }
+    }
+
+    /// Add a schema file, or a directory of them such as `GameData/kRPC`
+    pub fn input(mut self, path: impl AsRef<Path>) -> Self {
+        self.inputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Directory the bindings are written to. Defaults to `OUT_DIR`.
+    pub fn out_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.out_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Only generate the given service. Can be called several times; without
+    /// it every service in the inputs is generated.
+    pub fn service(mut self, name: impl Into<String>) -> Self {
+        self.services.push(name.into());
+        self
+    }
+
+    /// Generate server-side traits instead of client bindings
+    pub fn server(mut self, server: bool) -> Self {
+        self.server = server;
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Print `cargo:rerun-if-changed` for the inputs and the template
+    /// directory, so the build script only reruns when they change. On by
+    /// default.
+    pub fn emit_rerun_if_changed(mut self, emit: bool) -> Self {
+        self.rerun_if_changed = emit;
+        self
+    }
+
+    /// Print generator warnings, such as getters that keep their `get_`
+    /// prefix, as `cargo:warning`. On by default.
+    pub fn emit_warnings(mut self, emit: bool) -> Self {
+        self.warnings = emit;
+        self
+    }
+
+    /// Treat the out dir as owned by the generator: generated files of
+    /// services that are no longer generated are deleted. Only `.rs` files
+    /// starting with the generated-file header are touched.
+    pub fn managed(mut self, managed: bool) -> Self {
+        self.managed = managed;
+        self
+    }
+
+    /// Generate the bindings and return the paths of the generated files.
+    /// Files whose content did not change are left untouched.
+    pub fn generate(&self) -> Result<Vec<PathBuf>, Error> {
+        let out_dir = self.resolve_out_dir()?;
+
+        if self.rerun_if_changed {
+            for input in &self.inputs {
+                println!("cargo:rerun-if-changed={}", input.display());
+            }
+            if let Some(template_dir) = &self.options.template_dir {
+                println!("cargo:rerun-if-changed={}", template_dir.display());
+            }
+            if let Some(config) = &self.options.config {
+                println!("cargo:rerun-if-changed={}", config.display());
+            }
+        }
+
+        let rendered = self.render_files(&out_dir)?;
+        std::fs::create_dir_all(&out_dir)?;
+
+        let mut written = Vec::new();
+        for (path, source) in rendered {
+            if let Some(directory) = path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            writer::write_if_changed(&path, &source)?;
+            written.push(path);
+        }
+        if self.managed || self.options.managed {
+            writer::remove_stale_files(&out_dir, &written)?;
+        }
+        Ok(written)
+    }
+
+    /// Render the bindings in memory and compare them with the files in the
+    /// out dir without writing anything. Returns the files that are missing
+    /// or out of date.
+    pub fn check(&self) -> Result<Vec<Mismatch>, Error> {
+        let out_dir = self.resolve_out_dir()?;
+        let mut mismatches = Vec::new();
+        for (path, source) in self.render_files(&out_dir)? {
+            mismatches.extend(check::compare(&path, &source)?);
+        }
+        Ok(mismatches)
+    }
+
+    /// Render the bindings in memory, returning each service name with its
+    /// source code. Not available for client bindings with
+    /// `Layout::Modules`, which span several files per service.
+    pub fn render(&self) -> Result<Vec<(String, String)>, Error> {
+        if !self.server && self.options.layout == Layout::Modules {
+            return Err(Error::Unsupported("the modules layout renders several files per service"));
+        }
+        let config = Config::from_options(&self.options)?;
+        let mut rendered = Vec::new();
+        for (service_name, content) in self.load()? {
+            let output_structure = crate::convert(&service_name, content, &config, &self.options)?;
+            self.print_warnings(&output_structure);
+            let source = if self.server {
+                let server_structure = parser::create_server_structure(&output_structure);
+                writer::render_server(&service_name, &server_structure, &self.options)?
+            } else {
+                writer::render(&service_name, &output_structure, &self.options)?
+            };
+            rendered.push((service_name, source));
+        }
+        Ok(rendered)
+    }
+
+    /// Render the bindings in memory with the paths they are written to in
+    /// `out_dir`
+    fn render_files(&self, out_dir: &Path) -> Result<Vec<(PathBuf, String)>, Error> {
+        let config = Config::from_options(&self.options)?;
+        let mut rendered = Vec::new();
+        let mut output_structures = Vec::new();
+        for (service_name, content) in self.load()? {
+            let output_structure = crate::convert(&service_name, content, &config, &self.options)?;
+            self.print_warnings(&output_structure);
+            if self.server {
+                let server_structure = parser::create_server_structure(&output_structure);
+                let source = writer::render_server(&service_name, &server_structure, &self.options)?;
+                rendered.push((writer::service_path(out_dir, &service_name, Layout::File), source));
+            } else {
+                let path = writer::service_path(out_dir, &service_name, self.options.layout);
+                rendered.extend(writer::render_files(&service_name, &path, &output_structure, &self.options)?);
+                output_structures.push((service_name, output_structure));
+            }
+        }
+        if self.options.features && !self.server {
+            let emit = self.warnings;
+            rendered.extend(crate::render_features(out_dir, &output_structures, &self.options, |warning| if emit {
+                println!("cargo:warning={}", warning);
+            }));
+        }
+        Ok(rendered)
+    }
+
+    /// Build the client bindings as tokens with the tokens backend, for
+    /// procedural macros. Server stubs are only available from the templates.
+    pub fn render_tokens(&self) -> Result<Vec<(String, TokenStream)>, Error> {
+        if self.server {
+            return Err(Error::Unsupported("server stubs can't be generated as tokens"));
+        }
+        let config = Config::from_options(&self.options)?;
+        let mut rendered = Vec::new();
+        for (service_name, content) in self.load()? {
+            let output_structure = crate::convert(&service_name, content, &config, &self.options)?;
+            self.print_warnings(&output_structure);
+            let tokens = tokens::service_tokens(&service_name, &output_structure, &self.options)?;
+            rendered.push((service_name, tokens));
+        }
+        Ok(rendered)
+    }
+
+    fn print_warnings(&self, output_structure: &output::OutputStructure) {
+        if self.warnings {
+            for warning in &output_structure.warnings {
+                println!("cargo:warning={}", warning);
+            }
+        }
+    }
+
+    fn resolve_out_dir(&self) -> Result<PathBuf, Error> {
+        match &self.out_dir {
+            Some(out_dir) => Ok(out_dir.clone()),
+            None => std::env::var_os("OUT_DIR").map(PathBuf::from).ok_or(Error::MissingOutDir),
+        }
+    }
+
+    /// Load the inputs and keep the requested services that the include and
+    /// exclude patterns select, sorted by name
+    fn load(&self) -> Result<Vec<(String, original::Content)>, Error> {
+        let mut services = HashMap::new();
+        for input in &self.inputs {
+            services.extend(original::try_deserialize_from_path(input)?);
+        }
+
+        let mut selected: Vec<(String, original::Content)> = if self.services.is_empty() {
+            services.into_iter().collect()
+        } else {
+            let mut selected = Vec::new();
+            for name in &self.services {
+                let content = services.remove(name).ok_or_else(|| Error::ServiceNotFound(name.clone()))?;
+                selected.push((name.clone(), content));
+            }
+            selected
+        };
+        selected.retain(|(service_name, _)| filter::selects_service(&self.options, service_name));
+        selected.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+        "KRPC": {"id": 1, "procedures": {"GetClientID": {"id": 1, "parameters": [], "return_type": {"code": "BYTES"}}}, "classes": {}, "enumerations": {}},
+        "SpaceCenter": {"id": 2, "procedures": {"get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}}}, "classes": {}, "enumerations": {}}
+    }"#;
+
+    #[test]
+    fn test_builder() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_builder_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("schema.json");
+        std::fs::write(&input, SCHEMA).unwrap();
+        let out_dir = directory.join("out");
+
+        let written = Builder::new().input(&input).out_dir(&out_dir).emit_rerun_if_changed(false).generate().unwrap();
+        assert_eq!(written, vec![out_dir.join("krpc.rs"), out_dir.join("space_center.rs")]);
+
+        let written = Builder::new().input(&input).out_dir(&out_dir).service("SpaceCenter").emit_rerun_if_changed(false).generate().unwrap();
+        assert_eq!(written, vec![out_dir.join("space_center.rs")]);
+        assert!(std::fs::read_to_string(&written[0]).unwrap().contains("pub struct SpaceCenter<'a>"));
+
+        let builder = Builder::new().input(&input).out_dir(&out_dir).emit_rerun_if_changed(false);
+        assert!(builder.check().unwrap().is_empty());
+        std::fs::write(out_dir.join("krpc.rs"), "// stale").unwrap();
+        let mismatches = builder.check().unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].path, out_dir.join("krpc.rs"));
+        assert_eq!(std::fs::read_to_string(out_dir.join("krpc.rs")).unwrap(), "// stale");
+
+        std::fs::write(out_dir.join("drawing.rs"), format!("{}\npub struct Drawing;\n", writer::GENERATED_HEADER)).unwrap();
+        std::fs::write(out_dir.join("main.rs"), "fn main() {}\n").unwrap();
+        Builder::new().input(&input).out_dir(&out_dir).service("KRPC").managed(true).emit_rerun_if_changed(false).generate().unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&out_dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["krpc.rs", "main.rs"]);
+
+        let result = Builder::new().input(&input).out_dir(&out_dir).service("Drawing").emit_rerun_if_changed(false).generate();
+        assert!(matches!(result, Err(Error::ServiceNotFound(service)) if service == "Drawing"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_features() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_features_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("schema.json");
+        std::fs::write(&input, SCHEMA).unwrap();
+        let out_dir = directory.join("out");
+        let cargo_features = directory.join("features.toml");
+        let options = Options { features: true, cargo_features: Some(cargo_features.clone()), ..Options::default() };
+
+        let written = Builder::new().input(&input).out_dir(&out_dir).options(options).emit_rerun_if_changed(false).generate().unwrap();
+        assert_eq!(written, vec![out_dir.join("krpc.rs"), out_dir.join("space_center.rs"), out_dir.join("mod.rs"), cargo_features.clone()]);
+        let index = std::fs::read_to_string(out_dir.join("mod.rs")).unwrap();
+        assert!(index.contains("#[cfg(feature = \"space_center\")]\npub mod space_center {\n    include!(\"space_center.rs\");\n}\n"));
+        assert!(std::fs::read_to_string(&cargo_features).unwrap().ends_with("[features]\nkrpc = []\nspace_center = []\n"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_modules_layout() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_modules_layout_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("schema.json");
+        std::fs::write(&input, r#"{"SpaceCenter": {
+            "id": 2,
+            "procedures": {
+                "Vessel_get_Name": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }}"#).unwrap();
+        let out_dir = directory.join("out");
+        let options = Options { layout: Layout::Modules, ..Options::default() };
+
+        let service_dir = out_dir.join("space_center");
+        std::fs::create_dir_all(&service_dir).unwrap();
+        std::fs::write(service_dir.join("part.rs"), format!("{}\npub struct Part;\n", writer::GENERATED_HEADER)).unwrap();
+        std::fs::write(out_dir.join("space_center.rs"), format!("{}\npub struct SpaceCenter;\n", writer::GENERATED_HEADER)).unwrap();
+
+        let builder = Builder::new().input(&input).out_dir(&out_dir).options(options).managed(true).emit_rerun_if_changed(false);
+        let written = builder.generate().unwrap();
+        assert_eq!(written, vec![service_dir.join("mod.rs"), service_dir.join("vessel.rs")]);
+        assert!(!service_dir.join("part.rs").exists());
+        assert!(!out_dir.join("space_center.rs").exists());
+        assert!(builder.check().unwrap().is_empty());
+        assert!(matches!(builder.render(), Err(Error::Unsupported(_))));
+
+        std::fs::write(service_dir.join("vessel.rs"), "// stale").unwrap();
+        let mismatches = builder.check().unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].path, service_dir.join("vessel.rs"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_reproducible_output() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_reproducible_output_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let input = directory.join("schema.json");
+        std::fs::write(&input, r#"{"SpaceCenter": {
+            "id": 2,
+            "procedures": {
+                "Vessel_get_Name": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Part_get_Name": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Part"}}], "return_type": {"code": "STRING"}},
+                "Orbit_get_Body": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Orbit"}}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "CelestialBody"}}
+            },
+            "classes": {"Vessel": {}, "Part": {}, "Orbit": {}, "CelestialBody": {}, "Flight": {}, "Control": {}},
+            "enumerations": {
+                "VesselType": {"values": [{"name": "Ship", "value": 0}, {"name": "Probe", "value": 2}]},
+                "VesselSituation": {"values": [{"name": "Landed", "value": 0}]},
+                "SASMode": {"values": [{"name": "StabilityAssist", "value": 0}]},
+                "SpeedMode": {"values": [{"name": "Orbit", "value": 0}]}
+            }
+        }}"#).unwrap();
+
+        let builder = Builder::new().input(&input).emit_rerun_if_changed(false);
+        let first = builder.render().unwrap();
+        for _ in 0..10 {
+            assert_eq!(builder.render().unwrap(), first);
+        }
+        let source = &first[0].1;
+        let positions: Vec<usize> = ["pub struct CelestialBody", "pub struct Control", "pub struct Flight", "pub struct Orbit", "pub struct Part", "pub struct Vessel",
+            "pub enum SASMode", "pub enum SpeedMode", "pub enum VesselSituation", "pub enum VesselType"].iter()
+            .map(|item| source.find(item).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
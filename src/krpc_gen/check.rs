@@ -0,0 +1,63 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use similar::TextDiff;
+
+use crate::error::Error;
+
+/// A generated file whose content differs from what the generator renders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub path: PathBuf,
+    /// Unified diff from the file on disk to the freshly rendered code
+    pub diff: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.diff)
+    }
+}
+
+/// Compare the file at `path` with `expected` without writing it. A missing
+/// file counts as empty.
+pub(crate) fn compare(path: &Path, expected: &str) -> Result<Option<Mismatch>, Error> {
+    let actual = match std::fs::read_to_string(path) {
+        Ok(actual) => actual,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    if actual == expected {
+        return Ok(None);
+    }
+
+    let name = path.display().to_string();
+    let diff = TextDiff::from_lines(actual.as_str(), expected)
+        .unified_diff()
+        .header(&name, &format!("{} (generated)", name))
+        .to_string();
+    Ok(Some(Mismatch { path: path.to_path_buf(), diff }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_compare_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("space_center.rs");
+        std::fs::write(&path, "pub struct SpaceCenter;\npub fn get_ut() {}\n").unwrap();
+
+        assert_eq!(compare(&path, "pub struct SpaceCenter;\npub fn get_ut() {}\n").unwrap(), None);
+
+        let mismatch = compare(&path, "pub struct SpaceCenter;\npub fn ut() {}\n").unwrap().unwrap();
+        assert_eq!(mismatch.path, path);
+        assert!(mismatch.diff.contains("-pub fn get_ut() {}\n+pub fn ut() {}\n"));
+
+        let missing = compare(&directory.join("drawing.rs"), "pub struct Drawing;\n").unwrap().unwrap();
+        assert!(missing.diff.contains("+pub struct Drawing;\n"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
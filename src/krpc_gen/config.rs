@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::options::Options;
+use crate::original;
+
+/// Per-procedure overrides read from a `krpc-gen.toml`:
+///
+/// ```toml
+/// [services.SpaceCenter.procedures.Vessel_Position]
+/// rename = "position_in"
+/// return_type = "crate::Vector3"
+///
+/// [services.SpaceCenter.procedures.Vessel_Flight.parameters.referenceFrame]
+/// nullable = true
+///
+/// [services.SpaceCenter.classes.Vessel]
+/// derives = ["Clone", "Copy"]
+/// ```
+///
+/// The rules are applied to the schema before it is converted for the
+/// templates.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceRules>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceRules {
+    /// Text appended to the documentation
+    pub doc: Option<String>,
+    /// Keyed by the full procedure name, e.g. `Vessel_get_Name`
+    #[serde(default)]
+    pub procedures: BTreeMap<String, ProcedureRules>,
+    #[serde(default)]
+    pub classes: BTreeMap<String, TypeRules>,
+    #[serde(default)]
+    pub enumerations: BTreeMap<String, TypeRules>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProcedureRules {
+    /// Name of the generated method
+    pub rename: Option<String>,
+    #[serde(default)]
+    pub exclude: bool,
+    pub doc: Option<String>,
+    /// Attributes added to the method, without the `#[...]`
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    /// Rust type returned instead of the schema's, converted with `Into`
+    pub return_type: Option<String>,
+    /// Keyed by the parameter name in the schema
+    #[serde(default)]
+    pub parameters: BTreeMap<String, ParameterRules>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterRules {
+    pub nullable: Option<bool>,
+    /// Rust type taken instead of the schema's, converted with `Into`
+    pub r#type: Option<String>,
+}
+
+/// Rules for a class or an enumeration. Enumerations can't be excluded.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TypeRules {
+    #[serde(default)]
+    pub exclude: bool,
+    pub doc: Option<String>,
+    /// Derives added after the built-in ones
+    #[serde(default)]
+    pub derives: Vec<String>,
+    /// Attributes added to the type, without the `#[...]`
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+impl Config {
+    /// Read and validate a configuration file. Types, derives and attributes
+    /// must be valid Rust, so mistakes are reported here instead of as
+    /// invalid generated code.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let invalid = |message: String| Error::Config(path.to_path_buf(), message);
+        let text = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        config.validate().map_err(invalid)?;
+        Ok(config)
+    }
+
+    /// The file set in `options.config`, or no rules at all
+    pub fn from_options(options: &Options) -> Result<Config, Error> {
+        match &options.config {
+            Some(path) => Config::load(path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        fn check<T: syn::parse::Parse>(value: &str, what: &str, key: &str) -> Result<(), String> {
+            syn::parse_str::<T>(value)
+                .map(|_| ())
+                .map_err(|e| format!("{}: invalid {} `{}`: {}", key, what, value, e))
+        }
+        for (service_name, service) in &self.services {
+            for (name, procedure) in &service.procedures {
+                let key = format!("{}.{}", service_name, name);
+                if let Some(rename) = &procedure.rename {
+                    check::<syn::Ident>(rename, "method name", &key)?;
+                }
+                for attribute in &procedure.attributes {
+                    check::<syn::Meta>(attribute, "attribute", &key)?;
+                }
+                if let Some(return_type) = &procedure.return_type {
+                    check::<syn::Type>(return_type, "type", &key)?;
+                }
+                for (parameter_name, parameter) in &procedure.parameters {
+                    if let Some(r#type) = &parameter.r#type {
+                        check::<syn::Type>(r#type, "type", &format!("{}.{}", key, parameter_name))?;
+                    }
+                }
+            }
+            for (name, rules) in service.classes.iter().chain(&service.enumerations) {
+                let key = format!("{}.{}", service_name, name);
+                for derive in &rules.derives {
+                    check::<syn::Path>(derive, "derive", &key)?;
+                }
+                for attribute in &rules.attributes {
+                    check::<syn::Meta>(attribute, "attribute", &key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the rules for `service_name` to its schema. Returns warnings for
+    /// rules that don't match anything in the schema or can't be applied.
+    pub fn apply(&self, service_name: &str, content: &mut original::Content) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let service = match self.services.get(service_name) {
+            Some(service) => service,
+            None => return warnings,
+        };
+
+        if let Some(doc) = &service.doc {
+            append_doc(&mut content.documentation, doc);
+        }
+
+        for (name, rules) in &service.classes {
+            let class = match content.classes.get_mut(name) {
+                Some(class) => class,
+                None => {
+                    warnings.push(format!("config: class {}.{} not found", service_name, name));
+                    continue;
+                },
+            };
+            if rules.exclude {
+                content.classes.remove(name);
+                exclude_class(name, content, &mut warnings);
+                continue;
+            }
+            if let Some(doc) = &rules.doc {
+                append_doc(&mut class.documentation, doc);
+            }
+            class.derives.extend(rules.derives.iter().cloned());
+            class.attributes.extend(rules.attributes.iter().cloned());
+        }
+
+        for (name, rules) in &service.enumerations {
+            let enumeration = match content.enumerations.get_mut(name) {
+                Some(enumeration) => enumeration,
+                None => {
+                    warnings.push(format!("config: enumeration {}.{} not found", service_name, name));
+                    continue;
+                },
+            };
+            if rules.exclude {
+                warnings.push(format!("config: enumeration {}.{} can't be excluded", service_name, name));
+            }
+            if let Some(doc) = &rules.doc {
+                append_doc(&mut enumeration.documentation, doc);
+            }
+            enumeration.derives.extend(rules.derives.iter().cloned());
+            enumeration.attributes.extend(rules.attributes.iter().cloned());
+        }
+
+        for (name, rules) in &service.procedures {
+            let procedure = match content.procedures.get_mut(name) {
+                Some(procedure) => procedure,
+                None => {
+                    warnings.push(format!("config: procedure {}.{} not found", service_name, name));
+                    continue;
+                },
+            };
+            if rules.exclude {
+                procedure.excluded = true;
+                continue;
+            }
+            if let Some(rename) = &rules.rename {
+                procedure.rename = Some(rename.clone());
+            }
+            if let Some(doc) = &rules.doc {
+                append_doc(&mut procedure.documentation, doc);
+            }
+            procedure.attributes.extend(rules.attributes.iter().cloned());
+            match (&rules.return_type, &procedure.return_type) {
+                (Some(_), None) => warnings.push(format!("config: procedure {}.{} returns nothing, ignoring its return type", service_name, name)),
+                (Some(return_type), Some(_)) => procedure.return_type_override = Some(return_type.clone()),
+                (None, _) => {},
+            }
+
+            for (parameter_name, parameter_rules) in &rules.parameters {
+                let parameter = match procedure.parameters.iter_mut().find(|parameter| &parameter.name == parameter_name) {
+                    Some(parameter) => parameter,
+                    None => {
+                        warnings.push(format!("config: parameter {} of {}.{} not found", parameter_name, service_name, name));
+                        continue;
+                    },
+                };
+                if let Some(nullable) = parameter_rules.nullable {
+                    parameter.nullable = nullable;
+                }
+                match &parameter_rules.r#type {
+                    Some(_) if parameter.r#type.code == original::Code::Class => {
+                        warnings.push(format!("config: parameter {} of {}.{} is a class, ignoring its type", parameter_name, service_name, name));
+                    },
+                    Some(r#type) => parameter.type_override = Some(r#type.clone()),
+                    None => {},
+                }
+            }
+        }
+        warnings
+    }
+}
+
+/// Exclude the procedures of an excluded class, and the procedures that take
+/// or return it, which could not be generated without it
+fn exclude_class(class_name: &str, content: &mut original::Content, warnings: &mut Vec<String>) {
+    let prefix = format!("{}_", class_name);
+    let mut names: Vec<&String> = content.procedures.keys().collect();
+    names.sort();
+    let mut referencing = Vec::new();
+    for name in names {
+        let procedure = &content.procedures[name];
+        if procedure.excluded {
+            continue;
+        }
+        let references = procedure.parameters.iter().map(|parameter| &parameter.r#type)
+            .chain(&procedure.return_type)
+            .any(|r#type| references_class(r#type, class_name));
+        if name.starts_with(&prefix) {
+            referencing.push((name.clone(), false));
+        } else if references {
+            referencing.push((name.clone(), true));
+        }
+    }
+    for (name, warn) in referencing {
+        if warn {
+            warnings.push(format!("config: excluding {} because it uses the excluded class {}", name, class_name));
+        }
+        content.procedures.get_mut(&name).unwrap().excluded = true;
+    }
+}
+
+fn references_class(r#type: &original::Type, class_name: &str) -> bool {
+    (r#type.code == original::Code::Class && r#type.name.as_deref() == Some(class_name))
+        || r#type.types.iter().flatten().any(|t| references_class(t, class_name))
+}
+
+/// Append plain text to XML documentation, escaped so it comes out as written
+fn append_doc(documentation: &mut String, text: &str) {
+    let text = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    if documentation.is_empty() {
+        *documentation = text;
+    } else {
+        *documentation = format!("{}\n\n{}", documentation, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+        "id": 1,
+        "procedures": {
+            "WarpTo": {"id": 1, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}]},
+            "Vessel_Position": {
+                "id": 2,
+                "parameters": [
+                    {"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                    {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}}
+                ],
+                "return_type": {"code": "TUPLE", "types": [{"code": "DOUBLE"}, {"code": "DOUBLE"}, {"code": "DOUBLE"}]}
+            },
+            "Vessel_get_Parts": {
+                "id": 3,
+                "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}],
+                "return_type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]}
+            },
+            "Part_get_Name": {
+                "id": 4,
+                "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Part"}}],
+                "return_type": {"code": "STRING"}
+            }
+        },
+        "classes": {"Vessel": {}, "ReferenceFrame": {}, "Part": {}},
+        "enumerations": {}
+    }"#;
+
+    #[test]
+    fn test_apply() {
+        let config: Config = toml::from_str(r#"
+            [services.SpaceCenter.procedures.WarpTo]
+            rename = "warp"
+            attributes = ["must_use"]
+            doc = "Returns once Vec<f64> is done"
+
+            [services.SpaceCenter.procedures.Vessel_Position]
+            return_type = "crate::Vector3"
+            parameters.referenceFrame.nullable = true
+
+            [services.SpaceCenter.procedures.Missing]
+            exclude = true
+
+            [services.SpaceCenter.classes.Vessel]
+            derives = ["Clone", "Copy"]
+
+            [services.SpaceCenter.classes.Part]
+            exclude = true
+        "#).unwrap();
+        config.validate().unwrap();
+        let mut content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        let warnings = config.apply("SpaceCenter", &mut content);
+
+        let warp_to = &content.procedures["WarpTo"];
+        assert_eq!(warp_to.rename.as_deref(), Some("warp"));
+        assert_eq!(warp_to.attributes, ["must_use"]);
+        assert_eq!(warp_to.documentation, "Returns once Vec&lt;f64&gt; is done");
+
+        let position = &content.procedures["Vessel_Position"];
+        assert_eq!(position.return_type_override.as_deref(), Some("crate::Vector3"));
+        assert!(position.parameters[1].nullable);
+        assert_eq!(content.classes["Vessel"].derives, ["Clone", "Copy"]);
+
+        assert!(!content.classes.contains_key("Part"));
+        assert!(content.procedures["Part_get_Name"].excluded);
+        assert!(content.procedures["Vessel_get_Parts"].excluded);
+        assert_eq!(warnings, [
+            "config: excluding Vessel_get_Parts because it uses the excluded class Part",
+            "config: procedure SpaceCenter.Missing not found",
+        ]);
+
+        let mut unchanged: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        assert!(config.apply("Drawing", &mut unchanged).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config: Config = toml::from_str(r#"
+            [services.SpaceCenter.procedures.WarpTo]
+            return_type = "Vec<"
+        "#).unwrap();
+        assert!(config.validate().unwrap_err().starts_with("SpaceCenter.WarpTo: invalid type `Vec<`"));
+
+        assert!(toml::from_str::<Config>("[services.SpaceCenter.procedures.WarpTo]\nrenamed = \"warp\"\n").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use serde::Serialize;
+use crate::original;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    ServiceAdded,
+    ServiceRemoved,
+    ProcedureAdded,
+    ProcedureRemoved,
+    ParametersChanged,
+    ReturnTypeChanged,
+    ProcedureIdChanged,
+    ClassAdded,
+    ClassRemoved,
+    EnumerationAdded,
+    EnumerationRemoved,
+    EnumerationValueAdded,
+    EnumerationValueRemoved,
+    EnumerationValueChanged,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub service: String,
+    pub kind: ChangeKind,
+    pub item: String,
+    pub description: String,
+    /// Whether code written against the old generated bindings stops compiling
+    pub breaking: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    fn push(&mut self, service: &str, kind: ChangeKind, item: &str, description: String, breaking: bool) {
+        self.changes.push(Change {
+            service: service.to_string(),
+            kind,
+            item: item.to_string(),
+            description,
+            breaking,
+        });
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for change in &self.changes {
+            writeln!(f, "{} {}.{}: {}",
+                if change.breaking { "[BREAKING]" } else { "          " },
+                change.service,
+                change.item,
+                change.description)?;
+        }
+        let breaking = self.changes.iter().filter(|change| change.breaking).count();
+        writeln!(f, "{} changes, {} breaking", self.changes.len(), breaking)
+    }
+}
+
+pub(crate) fn diff(old: &HashMap<String, original::Content>, new: &HashMap<String, original::Content>) -> SchemaDiff {
+    let mut result = SchemaDiff::default();
+    for service in sorted_keys(old, new) {
+        match (old.get(service), new.get(service)) {
+            (Some(_), None) => result.push(service, ChangeKind::ServiceRemoved, service, "service removed".to_string(), true),
+            (None, Some(_)) => result.push(service, ChangeKind::ServiceAdded, service, "service added".to_string(), false),
+            (Some(old), Some(new)) => diff_service(&mut result, service, old, new),
+            (None, None) => {},
+        }
+    }
+    result
+}
+
+fn diff_service(result: &mut SchemaDiff, service: &str, old: &original::Content, new: &original::Content) {
+    for name in sorted_keys(&old.classes, &new.classes) {
+        match (old.classes.contains_key(name), new.classes.contains_key(name)) {
+            (true, false) => result.push(service, ChangeKind::ClassRemoved, name, "class removed".to_string(), true),
+            (false, true) => result.push(service, ChangeKind::ClassAdded, name, "class added".to_string(), false),
+            _ => {},
+        }
+    }
+
+    for name in sorted_keys(&old.enumerations, &new.enumerations) {
+        match (old.enumerations.get(name), new.enumerations.get(name)) {
+            (Some(_), None) => result.push(service, ChangeKind::EnumerationRemoved, name, "enumeration removed".to_string(), true),
+            (None, Some(_)) => result.push(service, ChangeKind::EnumerationAdded, name, "enumeration added".to_string(), false),
+            (Some(old), Some(new)) => diff_enumeration(result, service, name, old, new),
+            (None, None) => {},
+        }
+    }
+
+    for name in sorted_keys(&old.procedures, &new.procedures) {
+        match (old.procedures.get(name), new.procedures.get(name)) {
+            (Some(_), None) => result.push(service, ChangeKind::ProcedureRemoved, name, "procedure removed".to_string(), true),
+            (None, Some(_)) => result.push(service, ChangeKind::ProcedureAdded, name, "procedure added".to_string(), false),
+            (Some(old), Some(new)) => diff_procedure(result, service, name, old, new),
+            (None, None) => {},
+        }
+    }
+}
+
+fn diff_enumeration(result: &mut SchemaDiff, service: &str, name: &str, old: &original::Enumeration, new: &original::Enumeration) {
+    for old_value in &old.values {
+        let item = format!("{}.{}", name, old_value.name);
+        match new.values.iter().find(|value| value.name == old_value.name) {
+            None => result.push(service, ChangeKind::EnumerationValueRemoved, &item, "value removed".to_string(), true),
+            Some(new_value) if new_value.value != old_value.value => {
+                // Generated code refers to variants by name, so renumbering is source compatible
+                result.push(service, ChangeKind::EnumerationValueChanged, &item,
+                    format!("value changed from {} to {}", old_value.value, new_value.value), false);
+            },
+            Some(_) => {},
+        }
+    }
+    for new_value in &new.values {
+        if !old.values.iter().any(|value| value.name == new_value.name) {
+            let item = format!("{}.{}", name, new_value.name);
+            // Generated enums are exhaustive, so a new variant breaks `match`es on them
+            result.push(service, ChangeKind::EnumerationValueAdded, &item, "value added".to_string(), true);
+        }
+    }
+}
+
+fn diff_procedure(result: &mut SchemaDiff, service: &str, name: &str, old: &original::Procedure, new: &original::Procedure) {
+    let old_parameters = parameters_signature(old);
+    let new_parameters = parameters_signature(new);
+    if old_parameters != new_parameters {
+        let old_types: Vec<&String> = old_parameters.iter().map(|(_, t)| t).collect();
+        let new_types: Vec<&String> = new_parameters.iter().map(|(_, t)| t).collect();
+        // Renaming a parameter does not affect callers of the generated method,
+        // but its type, nullability and default do
+        result.push(service, ChangeKind::ParametersChanged, name,
+            format!("parameters changed from ({}) to ({})", format_parameters(&old_parameters), format_parameters(&new_parameters)),
+            old_types != new_types);
+    }
+
+    let old_return = return_type_name(&old.return_type);
+    let new_return = return_type_name(&new.return_type);
+    if old_return != new_return {
+        result.push(service, ChangeKind::ReturnTypeChanged, name,
+            format!("return type changed from {} to {}", old_return, new_return), true);
+    }
+
+    if old.id != new.id {
+        result.push(service, ChangeKind::ProcedureIdChanged, name,
+            format!("procedure id changed from {} to {}", old.id, new.id), false);
+    }
+}
+
+/// Name and type of each parameter. The type is marked `nullable`, which
+/// makes it an `Option`, and `= default` for parameters with a default
+/// value, which move into the arguments struct of the method.
+fn parameters_signature(procedure: &original::Procedure) -> Vec<(String, String)> {
+    procedure.parameters.iter()
+        .map(|parameter| {
+            let mut r#type = type_name(&parameter.r#type.code, &parameter.r#type.service, &parameter.r#type.name, &parameter.r#type.types);
+            if parameter.nullable {
+                r#type += " nullable";
+            }
+            if parameter.default_value.is_some() {
+                r#type += " = default";
+            }
+            (parameter.name.clone(), r#type)
+        })
+        .collect()
+}
+
+fn format_parameters(parameters: &[(String, String)]) -> String {
+    parameters.iter()
+        .map(|(name, r#type)| format!("{}: {}", name, r#type))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn return_type_name(return_type: &Option<original::Type>) -> String {
+    match return_type {
+        Some(r#type) => type_name(&r#type.code, &r#type.service, &r#type.name, &r#type.types),
+        None => "NONE".to_string(),
+    }
+}
+
+fn type_name(code: &original::Code, service: &Option<String>, name: &Option<String>, types: &Option<Vec<original::Type>>) -> String {
+    let mut result = code.name().to_string();
+    if let Some(name) = name {
+        result += &format!(" {}.{}", service.clone().unwrap_or_default(), name);
+    }
+    if let Some(types) = types {
+        let inner: Vec<String> = types.iter()
+            .map(|t| type_name(&t.code, &t.service, &t.name, &t.types))
+            .collect();
+        result += &format!("({})", inner.join(", "));
+    }
+    result
+}
+
+fn sorted_keys<'a, V>(old: &'a HashMap<String, V>, new: &'a HashMap<String, V>) -> BTreeSet<&'a String> {
+    old.keys().chain(new.keys()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(procedures: &str, enumerations: &str) -> HashMap<String, original::Content> {
+        let json = format!(r#"{{"SpaceCenter": {{
+            "id": 1,
+            "procedures": {{{}}},
+            "classes": {{"Vessel": {{}}}},
+            "enumerations": {{{}}}
+        }}}}"#, procedures, enumerations);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = schema(r#"
+            "Vessel_get_Name": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+            "Vessel_Recover": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}]},
+            "WarpTo": {"id": 3, "parameters": [{"name": "ut", "type": {"code": "FLOAT"}}]}
+        "#, r#"
+            "VesselType": {"values": [{"name": "Ship", "value": 0}, {"name": "Probe", "value": 1}]}
+        "#);
+        let new = schema(r#"
+            "Vessel_get_Name": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+            "WarpTo": {"id": 3, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}]},
+            "get_UT": {"id": 5, "parameters": [], "return_type": {"code": "DOUBLE"}}
+        "#, r#"
+            "VesselType": {"values": [{"name": "Ship", "value": 0}, {"name": "Station", "value": 2}]}
+        "#);
+
+        let result = diff(&old, &new);
+        let kinds: Vec<(ChangeKind, &str, bool)> = result.changes.iter()
+            .map(|change| (change.kind, change.item.as_str(), change.breaking))
+            .collect();
+        assert_eq!(kinds, vec![
+            (ChangeKind::EnumerationValueRemoved, "VesselType.Probe", true),
+            (ChangeKind::EnumerationValueAdded, "VesselType.Station", true),
+            (ChangeKind::ProcedureRemoved, "Vessel_Recover", true),
+            (ChangeKind::ProcedureIdChanged, "Vessel_get_Name", false),
+            (ChangeKind::ParametersChanged, "WarpTo", true),
+            (ChangeKind::ProcedureAdded, "get_UT", false),
+        ]);
+        assert!(result.is_breaking());
+        assert_eq!(result.changes[4].description, "parameters changed from (ut: FLOAT) to (ut: DOUBLE)");
+    }
+
+    #[test]
+    fn test_parameter_changes() {
+        let old = schema(r#"
+            "Vessel_Flight": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}, "nullable": true}]},
+            "WarpTo": {"id": 2, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}, {"name": "maxRate", "type": {"code": "FLOAT"}, "default_value": "AADIQg=="}]},
+            "AddStream": {"id": 3, "parameters": [{"name": "call", "type": {"code": "PROCEDURE_CALL"}}]}
+        "#, "");
+        let new = schema(r#"
+            "Vessel_Flight": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}}]},
+            "WarpTo": {"id": 2, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}, {"name": "maxRate", "type": {"code": "FLOAT"}}]},
+            "AddStream": {"id": 3, "parameters": [{"name": "procedureCall", "type": {"code": "PROCEDURE_CALL"}}]}
+        "#, "");
+
+        let result = diff(&old, &new);
+        let descriptions: Vec<(&str, &str, bool)> = result.changes.iter()
+            .map(|change| (change.item.as_str(), change.description.as_str(), change.breaking))
+            .collect();
+        assert_eq!(descriptions, vec![
+            ("AddStream", "parameters changed from (call: PROCEDURE_CALL) to (procedureCall: PROCEDURE_CALL)", false),
+            ("Vessel_Flight", "parameters changed from (this: CLASS SpaceCenter.Vessel, referenceFrame: CLASS SpaceCenter.ReferenceFrame nullable) to (this: CLASS SpaceCenter.Vessel, referenceFrame: CLASS SpaceCenter.ReferenceFrame)", true),
+            ("WarpTo", "parameters changed from (ut: DOUBLE, maxRate: FLOAT = default) to (ut: DOUBLE, maxRate: FLOAT)", true),
+        ]);
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let old = schema("", "");
+        let result = diff(&old, &old.clone());
+        assert!(result.is_empty());
+        assert_eq!(result.to_string(), "No changes\n");
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Decode(prost::DecodeError),
+    /// The server refused the connection request
+    Connection(String),
+    /// The server returned an error for a procedure call
+    Procedure(String),
+    UnsupportedTypeCode(i32),
+    /// The schema returned by `KRPC.GetServices` can't be converted
+    InvalidSchema(String),
+    /// A schema file is not valid kRPC schema JSON
+    Schema(std::path::PathBuf, serde_json::Error),
+    /// A requested service is not part of the schema
+    ServiceNotFound(String),
+    /// No output directory was given and `OUT_DIR` is not set
+    MissingOutDir,
+    /// The rendered code is not valid Rust
+    InvalidCode(Box<InvalidCode>),
+    /// `rustfmt` could not be run or rejected the code
+    Rustfmt(String),
+    /// The requested combination of settings is not supported
+    Unsupported(&'static str),
+    /// A procedure maps to a method name that is already used, with
+    /// `Collisions::Error`
+    NameCollision { procedure: String, name: String },
+    /// The configuration file is not valid
+    Config(std::path::PathBuf, String),
+    /// A service uses a class or enumeration of another service named like
+    /// one of its own
+    TypeClash { service: String, foreign_type: String },
+    /// A template could not be read or parsed, with its file name relative
+    /// to the template directory
+    Template(String, String),
+    /// The tokens backend got a name, attribute or type that is not valid
+    /// Rust
+    Tokens(String),
+}
+
+/// Where rendered code that is not valid Rust came from. `procedure` and
+/// `template_line` are filled in when they can be found.
+#[derive(Debug)]
+pub struct InvalidCode {
+    pub service: String,
+    /// Position of the error in the rendered file
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub procedure: Option<String>,
+    /// Template file name, relative to the template directory
+    pub template: String,
+    pub template_line: Option<usize>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::Connection(message) => write!(f, "connection refused: {}", message),
+            Error::Procedure(message) => write!(f, "procedure call failed: {}", message),
+            Error::UnsupportedTypeCode(code) => write!(f, "unsupported type code {}", code),
+            Error::InvalidSchema(message) => write!(f, "invalid schema from the server: {}", message),
+            Error::Schema(path, e) => write!(f, "invalid schema {}: {}", path.display(), e),
+            Error::ServiceNotFound(service) => write!(f, "service {} not found in the schema", service),
+            Error::MissingOutDir => write!(f, "no output directory given and OUT_DIR is not set"),
+            Error::InvalidCode(e) => {
+                write!(f, "generated code for {} does not parse at line {}:{}: {}", e.service, e.line, e.column, e.message)?;
+                if let Some(procedure) = &e.procedure {
+                    write!(f, ", generating procedure {}", procedure)?;
+                }
+                match e.template_line {
+                    Some(template_line) => write!(f, " (template {} line {})", e.template, template_line),
+                    None => write!(f, " (template {})", e.template),
+                }
+            },
+            Error::Rustfmt(message) => write!(f, "rustfmt failed: {}", message),
+            Error::Unsupported(message) => write!(f, "unsupported: {}", message),
+            Error::Config(path, message) => write!(f, "invalid configuration {}: {}", path.display(), message),
+            Error::TypeClash { service, foreign_type } => write!(f, "{} uses {}, which has the same name as a type of {}", service, foreign_type, service),
+            Error::Template(template, message) => write!(f, "invalid template {}: {}", template, message),
+            Error::Tokens(message) => write!(f, "tokens backend: {}", message),
+            Error::NameCollision { procedure, name } => write!(f, "procedure {} maps to `{}`, which is already used by another method", procedure, name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
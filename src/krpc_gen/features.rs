@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+use convert_case::{Case, Casing};
+
+use crate::error::Error;
+use crate::options::Layout;
+use crate::original;
+use crate::output;
+use crate::writer;
+
+/// Module of a service, which is also the name of its cargo feature:
+/// `SpaceCenter` becomes `space_center`
+pub fn service_module(service_name: &str) -> String {
+    service_name.to_case(Case::Snake)
+}
+
+/// Classes and enumerations of other services that the procedures of
+/// `service_name` take or return, directly or inside a collection or tuple.
+/// A type named like one of the service's own can't be imported and fails
+/// with `Error::TypeClash`, as the generated code would take it for the
+/// service's type.
+pub fn foreign_types(service_name: &str, content: &original::Content) -> Result<Vec<output::ForeignType>, Error> {
+    let mut types = BTreeSet::new();
+    for procedure in content.procedures.values().filter(|procedure| !procedure.excluded) {
+        let procedure_types = procedure.parameters.iter().map(|parameter| &parameter.r#type).chain(&procedure.return_type);
+        for r#type in procedure_types {
+            collect_foreign_types(r#type, service_name, &mut types);
+        }
+    }
+
+    if let Some(foreign_type) = types.iter().find(|foreign_type| content.classes.contains_key(&foreign_type.name) || content.enumerations.contains_key(&foreign_type.name)) {
+        return Err(Error::TypeClash {
+            service: service_name.to_string(),
+            foreign_type: format!("{}.{}", foreign_type.service, foreign_type.name),
+        });
+    }
+    Ok(types.into_iter().collect())
+}
+
+fn collect_foreign_types(r#type: &original::Type, service_name: &str, types: &mut BTreeSet<output::ForeignType>) {
+    if let (original::Code::Class | original::Code::Enumeration, Some(service), Some(name)) = (&r#type.code, &r#type.service, &r#type.name) {
+        if service != service_name {
+            types.insert(output::ForeignType {
+                service: service.clone(),
+                module: service_module(service),
+                name: name.clone(),
+            });
+        }
+    }
+    for r#type in r#type.types.iter().flatten() {
+        collect_foreign_types(r#type, service_name, types);
+    }
+}
+
+/// The cargo feature of every generated service, keyed by service module,
+/// with the features of the services whose types it uses. Services that
+/// are not generated can't be enabled and are reported as warnings.
+pub fn features<'a>(services: impl IntoIterator<Item = (&'a str, &'a output::OutputStructure)>) -> (BTreeMap<String, BTreeSet<String>>, Vec<String>) {
+    let services: BTreeMap<&str, &output::OutputStructure> = services.into_iter().collect();
+    let mut features = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for (service_name, output_structure) in &services {
+        let mut dependencies = BTreeSet::new();
+        for foreign_type in &output_structure.foreign_types {
+            if services.contains_key(foreign_type.service.as_str()) {
+                dependencies.insert(foreign_type.module.clone());
+            } else {
+                warnings.push(format!("{} uses {}.{}, but {} is not generated", service_name, foreign_type.service, foreign_type.name, foreign_type.service));
+            }
+        }
+        features.insert(service_module(service_name), dependencies);
+    }
+    (features, warnings)
+}
+
+/// `mod.rs` declaring the module of every service behind its feature. Each
+/// module includes the generated file of the service, so the index works
+/// both from `src` and from `OUT_DIR`.
+pub fn render_index(features: &BTreeMap<String, BTreeSet<String>>, layout: Layout) -> String {
+    let mut index = format!("{}\n#[allow(unused_imports)]\nuse super::schema;\n", writer::GENERATED_HEADER);
+    for module in features.keys() {
+        let file = match layout {
+            Layout::File => format!("{}.rs", module),
+            Layout::Modules => format!("{}/mod.rs", module),
+        };
+        index += &format!("\n#[cfg(feature = \"{}\")]\npub mod {} {{\n    include!(\"{}\");\n}}\n", module, module, file);
+    }
+    index
+}
+
+/// `[features]` table for the `Cargo.toml` of the crate holding the
+/// bindings
+pub fn render_cargo_features(features: &BTreeMap<String, BTreeSet<String>>) -> String {
+    let mut table = "# Generated by krpc-gen\n[features]\n".to_string();
+    for (feature, dependencies) in features {
+        let dependencies: Vec<String> = dependencies.iter().map(|dependency| format!("\"{}\"", dependency)).collect();
+        table += &format!("{} = [{}]\n", feature, dependencies.join(", "));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 3,
+            "procedures": {
+                "AddLine": {"id": 1, "parameters": [{"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}}], "return_type": {"code": "CLASS", "service": "Drawing", "name": "Line"}},
+                "Line_get_Parts": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "Drawing", "name": "Line"}}], "return_type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]}},
+                "Line_get_Alarm": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "Drawing", "name": "Line"}}], "return_type": {"code": "CLASS", "service": "KerbalAlarmClock", "name": "Alarm"}}
+            },
+            "classes": {"Line": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let foreign_types = foreign_types("Drawing", &content).unwrap();
+        let names: Vec<String> = foreign_types.iter().map(|foreign_type| format!("{}::{}", foreign_type.module, foreign_type.name)).collect();
+        assert_eq!(names, ["kerbal_alarm_clock::Alarm", "space_center::Part", "space_center::ReferenceFrame"]);
+
+        let drawing = output::OutputStructure { foreign_types, ..Default::default() };
+        let space_center = output::OutputStructure::default();
+        let (features, warnings) = features([("Drawing", &drawing), ("SpaceCenter", &space_center)]);
+        assert_eq!(warnings, ["Drawing uses KerbalAlarmClock.Alarm, but KerbalAlarmClock is not generated"]);
+        assert_eq!(render_cargo_features(&features), "# Generated by krpc-gen\n[features]\ndrawing = [\"space_center\"]\nspace_center = []\n");
+
+        let index = render_index(&features, Layout::Modules);
+        assert!(index.starts_with(writer::GENERATED_HEADER));
+        assert!(index.contains("#[cfg(feature = \"drawing\")]\npub mod drawing {\n    include!(\"drawing/mod.rs\");\n}\n"));
+        assert!(syn::parse_file(&index).is_ok());
+    }
+
+    #[test]
+    fn test_type_clash() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 3,
+            "procedures": {
+                "Line_get_Text": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "Drawing", "name": "Line"}}], "return_type": {"code": "CLASS", "service": "UI", "name": "Line"}}
+            },
+            "classes": {"Line": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        match foreign_types("Drawing", &content) {
+            Err(Error::TypeClash { service, foreign_type }) => assert_eq!((service.as_str(), foreign_type.as_str()), ("Drawing", "UI.Line")),
+            other => panic!("expected a type clash, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+use regex::Regex;
+
+use crate::options::Options;
+use crate::original;
+
+/// `Service` or `Service.Name`, where both parts are globs with `*` and `?`.
+/// `Name` matches procedure names, such as `Vessel_get_*`, and class names,
+/// which select every procedure of the class.
+struct Pattern {
+    service: Regex,
+    name: Option<Regex>,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Pattern {
+        let (service, name) = match pattern.split_once('.') {
+            Some((service, name)) => (service, Some(name)),
+            None => (pattern, None),
+        };
+        Pattern {
+            service: glob(service),
+            name: name.map(glob),
+        }
+    }
+
+    fn matches_procedure(&self, service_name: &str, procedure_name: &str, class_name: Option<&str>) -> bool {
+        self.service.is_match(service_name) && match &self.name {
+            Some(name) => name.is_match(procedure_name) || class_name.is_some_and(|class_name| name.is_match(class_name)),
+            None => true,
+        }
+    }
+}
+
+fn glob(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", ".");
+    Regex::new(&format!("^{}$", pattern)).unwrap()
+}
+
+fn patterns(patterns: &[String]) -> Vec<Pattern> {
+    patterns.iter().map(|pattern| Pattern::parse(pattern)).collect()
+}
+
+/// Whether anything of the service is generated: with include patterns it
+/// must be named by one of them, and it must not be excluded as a whole
+pub fn selects_service(options: &Options, service_name: &str) -> bool {
+    let included = options.include.is_empty() || patterns(&options.include).iter()
+        .any(|pattern| pattern.service.is_match(service_name));
+    let excluded = patterns(&options.exclude).iter()
+        .any(|pattern| pattern.name.is_none() && pattern.service.is_match(service_name));
+    included && !excluded
+}
+
+/// Exclude the procedures not selected by `options.include` and
+/// `options.exclude`, then drop the classes and enumerations the remaining
+/// procedures don't use. Without patterns the schema is left as it is.
+pub fn apply(options: &Options, service_name: &str, content: &mut original::Content) {
+    if options.include.is_empty() && options.exclude.is_empty() {
+        return;
+    }
+    let include = patterns(&options.include);
+    let exclude = patterns(&options.exclude);
+    let known_classes = &content.classes;
+
+    let mut classes = BTreeSet::new();
+    let mut enumerations = BTreeSet::new();
+    for (name, procedure) in content.procedures.iter_mut() {
+        let class_name = name.split_once('_')
+            .map(|(prefix, _)| prefix)
+            .filter(|prefix| known_classes.contains_key(*prefix));
+        let included = include.is_empty() || include.iter().any(|pattern| pattern.matches_procedure(service_name, name, class_name));
+        let excluded = exclude.iter().any(|pattern| pattern.matches_procedure(service_name, name, class_name));
+        if !included || excluded {
+            procedure.excluded = true;
+        }
+        if procedure.excluded {
+            continue;
+        }
+
+        // The methods of a class are generated in its impl
+        classes.extend(class_name.map(str::to_string));
+        let types = procedure.parameters.iter().map(|parameter| &parameter.r#type).chain(&procedure.return_type);
+        for r#type in types {
+            collect_types(r#type, service_name, &mut classes, &mut enumerations);
+        }
+    }
+
+    content.classes.retain(|name, _| classes.contains(name));
+    content.enumerations.retain(|name, _| enumerations.contains(name));
+}
+
+/// Classes and enumerations of `service_name` used by a type, including the
+/// element types of collections and tuples
+fn collect_types(r#type: &original::Type, service_name: &str, classes: &mut BTreeSet<String>, enumerations: &mut BTreeSet<String>) {
+    let own = r#type.service.as_deref().is_none_or(|service| service == service_name);
+    match (&r#type.code, &r#type.name) {
+        (original::Code::Class, Some(name)) if own => { classes.insert(name.clone()); },
+        (original::Code::Enumeration, Some(name)) if own => { enumerations.insert(name.clone()); },
+        _ => {},
+    }
+    for r#type in r#type.types.iter().flatten() {
+        collect_types(r#type, service_name, classes, enumerations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "get_ActiveVessel": {"id": 2, "parameters": [], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                "Vessel_get_Name": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Vessel_get_Parts": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Parts"}},
+                "Parts_get_All": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Parts"}}], "return_type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]}},
+                "Part_get_Type": {"id": 6, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Part"}}], "return_type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "PartType"}},
+                "Vessel_get_Situation": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "VesselSituation"}}
+            },
+            "classes": {"Vessel": {}, "Parts": {}, "Part": {}, "Orbit": {}},
+            "enumerations": {"PartType": {"values": []}, "VesselSituation": {"values": []}}
+        }"#).unwrap();
+        let options = Options {
+            include: vec!["SpaceCenter.get_ActiveVessel".to_string(), "SpaceCenter.Parts".to_string(), "SpaceCenter.Vessel_get_*".to_string()],
+            exclude: vec!["SpaceCenter.Vessel_get_Situation".to_string()],
+            ..Options::default()
+        };
+        apply(&options, "SpaceCenter", &mut content);
+
+        let mut kept: Vec<&str> = content.procedures.iter()
+            .filter(|(_, procedure)| !procedure.excluded)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        kept.sort();
+        assert_eq!(kept, ["Parts_get_All", "Vessel_get_Name", "Vessel_get_Parts", "get_ActiveVessel"]);
+        let mut classes: Vec<&str> = content.classes.keys().map(String::as_str).collect();
+        classes.sort();
+        assert_eq!(classes, ["Part", "Parts", "Vessel"]);
+        assert!(content.enumerations.is_empty());
+
+        assert!(selects_service(&options, "SpaceCenter"));
+        assert!(!selects_service(&options, "Drawing"));
+        let options = Options { exclude: vec!["Drawing".to_string()], ..Options::default() };
+        assert!(selects_service(&options, "SpaceCenter"));
+        assert!(!selects_service(&options, "Drawing"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::options::Format;
+
+#[derive(Debug)]
+pub enum FormatError {
+    /// The code is not valid Rust
+    Parse { line: usize, column: usize, message: String },
+    /// `rustfmt` could not be run or failed
+    Rustfmt(String),
+}
+
+/// Parse `source` and format it as requested. Leading `//` comment lines,
+/// such as the generated-file header, are kept as they are.
+pub fn format(source: &str, format: Format) -> Result<String, FormatError> {
+    if format == Format::None {
+        return Ok(source.to_string());
+    }
+
+    let file = syn::parse_file(source).map_err(|e| {
+        let start = e.span().start();
+        FormatError::Parse { line: start.line, column: start.column + 1, message: e.to_string() }
+    })?;
+
+    match format {
+        Format::PrettyPlease => {
+            let header: String = source.lines()
+                .take_while(|line| line.starts_with("//") && !line.starts_with("///"))
+                .map(|line| format!("{}\n", line))
+                .collect();
+            Ok(header + &prettyplease::unparse(&file))
+        },
+        Format::Rustfmt => rustfmt(source),
+        Format::None => unreachable!(),
+    }
+}
+
+fn rustfmt(source: &str) -> Result<String, FormatError> {
+    let mut child = Command::new("rustfmt")
+        .args(["--edition", "2018", "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| FormatError::Rustfmt(format!("failed to run rustfmt: {}", e)))?;
+    child.stdin.take().unwrap().write_all(source.as_bytes())
+        .map_err(|e| FormatError::Rustfmt(e.to_string()))?;
+    let output = child.wait_with_output()
+        .map_err(|e| FormatError::Rustfmt(e.to_string()))?;
+    if !output.status.success() {
+        return Err(FormatError::Rustfmt(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let source = "// Generated file DO NOT EDIT\n/// A vessel.\npub struct Vessel<'a> {\n\n\n        id: u64,  conn: &'a Connection,\n}\n";
+        assert_eq!(format(source, Format::PrettyPlease).unwrap(),
+            "// Generated file DO NOT EDIT\n/// A vessel.\npub struct Vessel<'a> {\n    id: u64,\n    conn: &'a Connection,\n}\n");
+        assert_eq!(format(source, Format::None).unwrap(), source);
+
+        match format("pub fn get_parts() -> Vec<Part> {\n    Ok((/*list*/))\n}}\n", Format::PrettyPlease) {
+            Err(FormatError::Parse { line, .. }) => assert_eq!(line, 3),
+            result => panic!("expected a parse error, got {:?}", result),
+        }
+    }
+}
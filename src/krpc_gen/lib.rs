@@ -1,26 +1,320 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
 mod original;
 mod parser;
 mod writer;
 mod output;
+mod live;
+mod error;
+mod options;
+mod builder;
+mod check;
+mod format;
+mod tokens;
+mod config;
+mod filter;
+mod features;
+pub mod diff;
+
+pub use error::{Error, InvalidCode};
+pub use options::{Backend, Collisions, Format, Layout, Options};
+pub use builder::Builder;
+pub use check::Mismatch;
+
+use config::Config;
 
 pub fn generate_for(path: &std::path::Path, output: &std::path::Path) {
+    generate_with_options(path, output, &Options::default()).unwrap_or_else(|e| panic!("{}", e));
+}
+
+pub fn generate_with_options(path: &std::path::Path, output: &std::path::Path, options: &Options) -> Result<(), Error> {
+
+    let input_structure = original::try_deserialize_from_path(path)?;
+    let config = Config::from_options(options)?;
+    let services = selected(input_structure, options);
+    let directory = services.len() > 1;
+    let files = render_services(services, output, directory, &config, options)?;
+    write_files(output, &files, directory, options)
+}
+
+/// Generate a server-side trait per service, with one method per procedure,
+/// plus a `dispatch` function that routes `ProcedureCall`s to it.
+pub fn generate_server_for(path: &std::path::Path, output: &std::path::Path) {
+    generate_server_with_options(path, output, &Options::default()).unwrap_or_else(|e| panic!("{}", e));
+}
+
+pub fn generate_server_with_options(path: &std::path::Path, output: &std::path::Path, options: &Options) -> Result<(), Error> {
+
+    let input_structure = original::try_deserialize_from_path(path)?;
+    let config = Config::from_options(options)?;
+
+    for (path, source) in render_servers(selected(input_structure, options), output, &config, options)? {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            std::fs::create_dir_all(directory)?;
+        }
+        writer::write_if_changed(&path, &source)?;
+    }
+    Ok(())
+}
+
+/// Render the bindings in memory and compare them with `output` without
+/// writing it. Returns a mismatch with a unified diff if `output` is missing
+/// or out of date.
+pub fn check_with_options(path: &std::path::Path, output: &std::path::Path, options: &Options) -> Result<Vec<Mismatch>, Error> {
+
+    let input_structure = original::try_deserialize_from_path(path)?;
+    let config = Config::from_options(options)?;
+
+    let services = selected(input_structure, options);
+    let directory = services.len() > 1;
+    let mut mismatches = Vec::new();
+    for (path, source) in render_services(services, output, directory, &config, options)? {
+        mismatches.extend(check::compare(&path, &source)?);
+    }
+    Ok(mismatches)
+}
+
+pub fn check_server_with_options(path: &std::path::Path, output: &std::path::Path, options: &Options) -> Result<Vec<Mismatch>, Error> {
+
+    let input_structure = original::try_deserialize_from_path(path)?;
+    let config = Config::from_options(options)?;
+
+    let mut mismatches = Vec::new();
+    for (path, source) in render_servers(selected(input_structure, options), output, &config, options)? {
+        mismatches.extend(check::compare(&path, &source)?);
+    }
+    Ok(mismatches)
+}
+
+/// Generate code for the services of a running kRPC server, retrieving the
+/// schema through `KRPC.GetServices` instead of the JSON files. `output` is
+/// a directory receiving a file per service. The server version is pinned
+/// unless `options` already sets `krpc_version`. `options.procedure_ids` is
+/// not supported, as `KRPC.GetServices` does not report ids.
+pub fn generate_from_server(address: impl std::net::ToSocketAddrs, output: &std::path::Path, options: &Options) -> Result<(), Error> {
+
+    if options.procedure_ids {
+        return Err(Error::Unsupported("procedure ids are not part of the KRPC.GetServices response"));
+    }
+    let schema = live::fetch_schema(address)?;
+    let options = Options {
+        krpc_version: options.krpc_version.clone().or(Some(schema.version)),
+        ..options.clone()
+    };
+    let config = Config::from_options(&options)?;
+    let files = render_services(selected(schema.services, &options), output, true, &config, &options)?;
+    write_files(output, &files, true, &options)
+}
+
+/// Compare two schema sets, each a schema file or a directory of them, and
+/// report the changes between them.
+pub fn diff_schemas(old: &std::path::Path, new: &std::path::Path) -> Result<diff::SchemaDiff, Error> {
+    let old_structure = original::try_deserialize_from_path(old)?;
+    let new_structure = original::try_deserialize_from_path(new)?;
+    Ok(diff::diff(&old_structure, &new_structure))
+}
 
-    let input_structure = original::deserialize_from_file(path);
+/// Apply the configuration file and the include and exclude patterns to the
+/// schema of a service and convert it for the templates, with the warnings of
+/// both steps
+fn convert(service_name: &str, mut content: original::Content, config: &Config, options: &Options) -> Result<output::OutputStructure, Error> {
+    let warnings = config.apply(service_name, &mut content);
+    filter::apply(options, service_name, &mut content);
+    let mut output_structure = parser::create_output_structure(&content, options)?;
+    // Only the modules declared with `features` are siblings to import from,
+    // so a type clash doesn't matter without them
+    if options.features {
+        output_structure.foreign_types = features::foreign_types(service_name, &content)?;
+    }
+    output_structure.warnings.splice(0..0, warnings);
+    Ok(output_structure)
+}
 
-    for (service_name, content) in input_structure {
-        let output_structure = parser::create_output_structure(&content);
-        writer::write_to_file(service_name.as_str(), &output, &output_structure);
+/// Render the client bindings of the services with the paths they are
+/// written to. With `directory` or `features`, `output` is a directory with
+/// a file per service (and with `features` their `mod.rs`); otherwise the
+/// single service is written to `output` itself. Schema files are rendered
+/// with `directory` when they hold more than one selected service.
+fn render_services(services: BTreeMap<String, original::Content>, output: &std::path::Path, directory: bool, config: &Config, options: &Options) -> Result<Vec<(PathBuf, String)>, Error> {
+    let mut output_structures = Vec::new();
+    for (service_name, content) in services {
+        let output_structure = convert(&service_name, content, config, options)?;
+        print_warnings(&output_structure);
+        output_structures.push((service_name, output_structure));
     }
 
-   
-    // for (getter, procedure) in &output_structure.getters {
-    //     println!("{:?}: {:?}", getter, procedure);
-    // }
-    // println!("{:?}", &output_structure.getters);
-    
-    
-    // println!("{:?}", v.space_center.procedures["get_ActiveVessel"]);
-    
-    // Convert into some more useable structure for code generation
-    
+    let mut files = Vec::new();
+    for (service_name, output_structure) in &output_structures {
+        let path = if directory || options.features {
+            writer::service_path(output, service_name, options.layout)
+        } else {
+            output.to_path_buf()
+        };
+        files.extend(writer::render_files(service_name, &path, output_structure, options)?);
+    }
+    if options.features {
+        files.extend(render_features(output, &output_structures, options, |warning| eprintln!("warning: {}", warning)));
+    }
+    Ok(files)
+}
+
+/// Render the server stubs of the services with the paths they are written
+/// to: `output` itself for a single service, otherwise a file per service in
+/// the directory `output`
+fn render_servers(services: BTreeMap<String, original::Content>, output: &std::path::Path, config: &Config, options: &Options) -> Result<Vec<(PathBuf, String)>, Error> {
+    let directory = services.len() > 1;
+    let mut files = Vec::new();
+    for (service_name, content) in services {
+        let output_structure = convert(&service_name, content, config, options)?;
+        print_warnings(&output_structure);
+        let server_structure = parser::create_server_structure(&output_structure);
+        let path = if directory {
+            writer::service_path(output, &service_name, Layout::File)
+        } else {
+            output.to_path_buf()
+        };
+        files.push((path, writer::render_server(&service_name, &server_structure, options)?));
+    }
+    Ok(files)
+}
+
+/// `mod.rs` of the services in `directory` and the `Cargo.toml` fragment
+/// if `options.cargo_features` asks for it
+fn render_features(directory: &std::path::Path, output_structures: &[(String, output::OutputStructure)], options: &Options, warn: impl Fn(&str)) -> Vec<(PathBuf, String)> {
+    let (features, warnings) = features::features(output_structures.iter().map(|(service_name, output_structure)| (service_name.as_str(), output_structure)));
+    for warning in &warnings {
+        warn(warning);
+    }
+    let mut files = vec![(directory.join("mod.rs"), features::render_index(&features, options.layout))];
+    if let Some(cargo_features) = &options.cargo_features {
+        files.push((cargo_features.clone(), features::render_cargo_features(&features)));
+    }
+    files
+}
+
+/// Write rendered files, creating their directories. With
+/// `options.managed` and `output` a directory, generated service files that
+/// were not rendered again are removed from it.
+fn write_files(output: &std::path::Path, files: &[(PathBuf, String)], directory: bool, options: &Options) -> Result<(), Error> {
+    for (path, source) in files {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            std::fs::create_dir_all(directory)?;
+        }
+        writer::write_if_changed(path, source)?;
+    }
+    if options.managed && (directory || options.features || options.layout == Layout::Modules) {
+        let keep: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+        writer::remove_stale_files(output, &keep)?;
+    }
+    Ok(())
+}
+
+fn print_warnings(output_structure: &output::OutputStructure) {
+    for warning in &output_structure.warnings {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+/// Services selected by the options, in name order so generating several
+/// services is reproducible
+fn selected(services: HashMap<String, original::Content>, options: &Options) -> BTreeMap<String, original::Content> {
+    services.into_iter()
+        .filter(|(service_name, _)| filter::selects_service(options, service_name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+        "Alpha": {"id": 1, "procedures": {"get_A": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}}}, "classes": {}, "enumerations": {"Mode": {"values": [{"name": "On", "value": 0}]}}},
+        "Beta": {"id": 2, "procedures": {
+            "get_B": {"id": 1, "parameters": [], "return_type": {"code": "STRING"}},
+            "get_Mode": {"id": 2, "parameters": [], "return_type": {"code": "ENUMERATION", "service": "Alpha", "name": "Mode"}}
+        }, "classes": {}, "enumerations": {}}
+    }"#;
+
+    #[test]
+    fn test_client_per_service() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_client_per_service_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let schema = directory.join("schema.json");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        let output = directory.join("client");
+
+        generate_with_options(&schema, &output, &Options::default()).unwrap();
+        assert!(std::fs::read_to_string(output.join("alpha.rs")).unwrap().contains("pub struct Alpha<'a>"));
+        let beta = std::fs::read_to_string(output.join("beta.rs")).unwrap();
+        assert!(beta.contains("pub struct Beta<'a>"));
+        // Sibling modules to import from are only declared with features
+        assert!(!beta.contains("use super::alpha::Mode;"));
+        assert!(check_with_options(&schema, &output, &Options::default()).unwrap().is_empty());
+
+        let options = Options { features: true, ..Options::default() };
+        generate_with_options(&schema, &output, &options).unwrap();
+        assert!(std::fs::read_to_string(output.join("beta.rs")).unwrap().contains("use super::alpha::Mode;"));
+
+        // A single selected service is still written to the output file
+        let options = Options { include: vec!["Alpha".to_string()], ..Options::default() };
+        let output = directory.join("alpha.rs");
+        generate_with_options(&schema, &output, &options).unwrap();
+        assert!(std::fs::read_to_string(&output).unwrap().contains("pub struct Alpha<'a>"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_type_clash_with_features() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_type_clash_with_features_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let schema = directory.join("schema.json");
+        let mut services: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        services["Beta"]["enumerations"]["Mode"] = serde_json::json!({"values": [{"name": "Off", "value": 0}]});
+        std::fs::write(&schema, services.to_string()).unwrap();
+        let output = directory.join("client");
+
+        generate_with_options(&schema, &output, &Options::default()).unwrap();
+        let options = Options { features: true, ..Options::default() };
+        let result = generate_with_options(&schema, &output, &options);
+        assert!(matches!(result, Err(Error::TypeClash { service, foreign_type }) if service == "Beta" && foreign_type == "Alpha.Mode"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_server_per_service() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_server_per_service_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let schema = directory.join("schema.json");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        let output = directory.join("server");
+
+        generate_server_with_options(&schema, &output, &Options::default()).unwrap();
+        assert!(std::fs::read_to_string(output.join("alpha.rs")).unwrap().contains("fn get_a("));
+        assert!(std::fs::read_to_string(output.join("beta.rs")).unwrap().contains("fn get_b("));
+        assert!(check_server_with_options(&schema, &output, &Options::default()).unwrap().is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_server_from_directory() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_server_from_directory_{}", std::process::id()));
+        let schemas = directory.join("schemas");
+        std::fs::create_dir_all(&schemas).unwrap();
+        let services: serde_json::Map<String, serde_json::Value> = serde_json::from_str(SCHEMA).unwrap();
+        for (service_name, content) in &services {
+            std::fs::write(schemas.join(format!("KRPC.{}.json", service_name)), serde_json::json!({service_name: content}).to_string()).unwrap();
+        }
+        let output = directory.join("server");
+
+        generate_server_with_options(&schemas, &output, &Options::default()).unwrap();
+        assert!(output.join("alpha.rs").exists());
+        assert!(output.join("beta.rs").exists());
+        assert!(check_server_with_options(&schemas, &output, &Options::default()).unwrap().is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 }
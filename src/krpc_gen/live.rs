@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use prost::Message;
+use crate::error::Error;
+use crate::original;
+
+// Subset of krpc.proto needed to call KRPC.GetServices and KRPC.GetStatus
+
+const CONNECTION_REQUEST_RPC: i32 = 0;
+const CONNECTION_RESPONSE_OK: i32 = 0;
+
+// A varint encodes a u64 in at most 10 bytes
+const MAX_VARINT_LENGTH: u32 = 10;
+// Largest message read from the server; the full GetServices response is a
+// few megabytes
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConnectionRequest {
+    #[prost(int32, tag = "1")]
+    pub r#type: i32,
+    #[prost(string, tag = "2")]
+    pub client_name: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub client_identifier: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConnectionResponse {
+    #[prost(int32, tag = "1")]
+    pub status: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub client_identifier: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Request {
+    #[prost(message, repeated, tag = "1")]
+    pub calls: Vec<ProcedureCall>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProcedureCall {
+    #[prost(string, tag = "1")]
+    pub service: String,
+    #[prost(string, tag = "2")]
+    pub procedure: String,
+    #[prost(uint32, tag = "4")]
+    pub service_id: u32,
+    #[prost(uint32, tag = "5")]
+    pub procedure_id: u32,
+    #[prost(message, repeated, tag = "3")]
+    pub arguments: Vec<Argument>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Argument {
+    #[prost(uint32, tag = "1")]
+    pub position: u32,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Response {
+    #[prost(message, optional, tag = "1")]
+    pub error: Option<ProcedureError>,
+    #[prost(message, repeated, tag = "2")]
+    pub results: Vec<ProcedureResult>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProcedureResult {
+    #[prost(message, optional, tag = "1")]
+    pub error: Option<ProcedureError>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProcedureError {
+    #[prost(string, tag = "1")]
+    pub service: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub description: String,
+    #[prost(string, tag = "4")]
+    pub stack_trace: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Status {
+    #[prost(string, tag = "1")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Services {
+    #[prost(message, repeated, tag = "1")]
+    pub services: Vec<Service>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Service {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub procedures: Vec<Procedure>,
+    #[prost(message, repeated, tag = "3")]
+    pub classes: Vec<Class>,
+    #[prost(message, repeated, tag = "4")]
+    pub enumerations: Vec<Enumeration>,
+    #[prost(string, tag = "6")]
+    pub documentation: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Procedure {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub parameters: Vec<Parameter>,
+    #[prost(message, optional, tag = "3")]
+    pub return_type: Option<Type>,
+    #[prost(bool, tag = "4")]
+    pub return_is_nullable: bool,
+    #[prost(string, tag = "5")]
+    pub documentation: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Parameter {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "2")]
+    pub r#type: Option<Type>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub default_value: Vec<u8>,
+    #[prost(bool, tag = "4")]
+    pub nullable: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Class {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub documentation: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Enumeration {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub values: Vec<EnumerationValue>,
+    #[prost(string, tag = "3")]
+    pub documentation: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EnumerationValue {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int32, tag = "2")]
+    pub value: i32,
+    #[prost(string, tag = "3")]
+    pub documentation: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Type {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub service: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(message, repeated, tag = "4")]
+    pub types: Vec<Type>,
+}
+
+/// Schema of a running server
+pub struct Schema {
+    pub services: HashMap<String, original::Content>,
+    pub version: String,
+}
+
+/// Connect to a running kRPC server and retrieve the schema of all its
+/// services through `KRPC.GetServices`, and its version through
+/// `KRPC.GetStatus`.
+pub fn fetch_schema(address: impl ToSocketAddrs) -> Result<Schema, Error> {
+    let mut stream = TcpStream::connect(address)?;
+
+    write_message(&mut stream, &ConnectionRequest {
+        r#type: CONNECTION_REQUEST_RPC,
+        client_name: "krpc-gen".to_string(),
+        client_identifier: vec![],
+    })?;
+    let response: ConnectionResponse = read_message(&mut stream)?;
+    if response.status != CONNECTION_RESPONSE_OK {
+        return Err(Error::Connection(response.message));
+    }
+
+    let value = call(&mut stream, "KRPC", "GetStatus")?;
+    let status = Status::decode(value.as_slice())?;
+    let value = call(&mut stream, "KRPC", "GetServices")?;
+    let services = Services::decode(value.as_slice())?;
+    Ok(Schema {
+        services: convert_services(&services)?,
+        version: status.version,
+    })
+}
+
+fn call(stream: &mut TcpStream, service: &str, procedure: &str) -> Result<Vec<u8>, Error> {
+    write_message(stream, &Request {
+        calls: vec![ProcedureCall {
+            service: service.to_string(),
+            procedure: procedure.to_string(),
+            ..Default::default()
+        }],
+    })?;
+    let response: Response = read_message(stream)?;
+    if let Some(error) = response.error {
+        return Err(Error::Procedure(error.description));
+    }
+    let result = response.results.into_iter().next()
+        .ok_or_else(|| Error::Procedure(format!("no result for {}.{}", service, procedure)))?;
+    if let Some(error) = result.error {
+        return Err(Error::Procedure(error.description));
+    }
+    Ok(result.value)
+}
+
+fn write_message(stream: &mut impl Write, message: &impl Message) -> Result<(), Error> {
+    stream.write_all(&message.encode_length_delimited_to_vec())?;
+    Ok(())
+}
+
+fn read_message<M: Message + Default>(stream: &mut impl Read) -> Result<M, Error> {
+    // Messages are prefixed with their size as a varint
+    let mut size: u64 = 0;
+    for index in 0.. {
+        if index == MAX_VARINT_LENGTH {
+            return Err(invalid_data("message size varint is too long".to_string()));
+        }
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        size |= ((byte[0] & 0x7f) as u64) << (7 * index);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    if size > MAX_MESSAGE_SIZE {
+        return Err(invalid_data(format!("message of {} bytes exceeds the limit of {} bytes", size, MAX_MESSAGE_SIZE)));
+    }
+    let size = size as usize;
+    let mut buffer = vec![0u8; size];
+    stream.read_exact(&mut buffer)?;
+    Ok(M::decode(buffer.as_slice())?)
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+// The response carries no service or procedure ids; they are left at 0 and
+// `generate_from_server` refuses to generate id-based calls.
+fn convert_services(services: &Services) -> Result<HashMap<String, original::Content>, Error> {
+    let mut result = HashMap::new();
+    for service in &services.services {
+        let mut procedures = HashMap::new();
+        for procedure in &service.procedures {
+            procedures.insert(procedure.name.clone(), original::Procedure {
+                id: 0,
+                parameters: procedure.parameters.iter()
+                    .map(convert_parameter)
+                    .collect::<Result<_, _>>()?,
+                game_scenes: None,
+                return_type: convert_return_type(&procedure.return_type)?,
+                return_is_nullable: Some(procedure.return_is_nullable),
+                documentation: procedure.documentation.clone(),
+                ..Default::default()
+            });
+        }
+        let classes = service.classes.iter()
+            .map(|class| (class.name.clone(), original::Class {
+                documentation: class.documentation.clone(),
+                ..Default::default()
+            }))
+            .collect();
+        let enumerations = service.enumerations.iter()
+            .map(|enumeration| Ok((enumeration.name.clone(), original::Enumeration {
+                values: enumeration.values.iter()
+                    .map(|value| Ok(original::EnumerationValue {
+                        name: value.name.clone(),
+                        value: u64::try_from(value.value).map_err(|_| Error::InvalidSchema(format!(
+                            "{}.{}.{} has the negative value {}", service.name, enumeration.name, value.name, value.value)))?,
+                        documentation: value.documentation.clone(),
+                    }))
+                    .collect::<Result<_, Error>>()?,
+                documentation: enumeration.documentation.clone(),
+                ..Default::default()
+            })))
+            .collect::<Result<_, Error>>()?;
+        result.insert(service.name.clone(), original::Content {
+            id: 0,
+            procedures,
+            classes,
+            enumerations,
+            documentation: service.documentation.clone(),
+        });
+    }
+    Ok(result)
+}
+
+fn convert_parameter(parameter: &Parameter) -> Result<original::Parameter, Error> {
+    let r#type = parameter.r#type.as_ref()
+        .ok_or_else(|| Error::InvalidSchema(format!("parameter {} has no type", parameter.name)))?;
+    Ok(original::Parameter {
+        name: parameter.name.clone(),
+        r#type: convert_type(r#type)?,
+        default_value: if parameter.default_value.is_empty() { None } else { Some(parameter.default_value.clone()) },
+        nullable: parameter.nullable,
+        ..Default::default()
+    })
+}
+
+fn convert_return_type(r#type: &Option<Type>) -> Result<Option<original::Type>, Error> {
+    match r#type {
+        Some(r#type) if r#type.code != 0 => Ok(Some(convert_type(r#type)?)),
+        _ => Ok(None),
+    }
+}
+
+fn convert_type(r#type: &Type) -> Result<original::Type, Error> {
+    let code = original::Code::from_type_code(r#type.code)
+        .ok_or(Error::UnsupportedTypeCode(r#type.code))?;
+    let types = r#type.types.iter()
+        .map(convert_type)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(original::Type {
+        code,
+        service: non_empty(&r#type.service),
+        name: non_empty(&r#type.name),
+        types: if types.is_empty() { None } else { Some(types) },
+    })
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() { None } else { Some(value.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn class_type(name: &str) -> Type {
+        Type { code: 100, service: "SpaceCenter".to_string(), name: name.to_string(), types: vec![] }
+    }
+
+    fn canned_services() -> Services {
+        Services {
+            services: vec![Service {
+                name: "SpaceCenter".to_string(),
+                procedures: vec![
+                    Procedure {
+                        name: "get_ActiveVessel".to_string(),
+                        return_type: Some(class_type("Vessel")),
+                        ..Default::default()
+                    },
+                    Procedure {
+                        name: "Vessel_get_Name".to_string(),
+                        parameters: vec![Parameter {
+                            name: "this".to_string(),
+                            r#type: Some(class_type("Vessel")),
+                            ..Default::default()
+                        }],
+                        return_type: Some(Type { code: 8, ..Default::default() }),
+                        ..Default::default()
+                    },
+                ],
+                classes: vec![Class { name: "Vessel".to_string(), ..Default::default() }],
+                enumerations: vec![Enumeration {
+                    name: "VesselType".to_string(),
+                    values: vec![EnumerationValue { name: "Ship".to_string(), value: 0, ..Default::default() }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    // Stand-in for a kRPC server that answers a single GetServices call
+    fn serve_once(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let _: ConnectionRequest = read_message(&mut stream).unwrap();
+        write_message(&mut stream, &ConnectionResponse::default()).unwrap();
+        for _ in 0..2 {
+            let request: Request = read_message(&mut stream).unwrap();
+            assert_eq!(request.calls[0].service, "KRPC");
+            let value = match request.calls[0].procedure.as_str() {
+                "GetStatus" => Status { version: "0.5.2".to_string() }.encode_to_vec(),
+                "GetServices" => canned_services().encode_to_vec(),
+                procedure => panic!("unexpected call to {}", procedure),
+            };
+            write_message(&mut stream, &Response {
+                error: None,
+                results: vec![ProcedureResult { error: None, value }],
+            }).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fetch_schema() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve_once(listener));
+
+        let schema = fetch_schema(address).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(schema.version, "0.5.2");
+        let content = &schema.services["SpaceCenter"];
+        assert!(content.procedures.contains_key("get_ActiveVessel"));
+        assert_eq!(content.procedures["Vessel_get_Name"].parameters[0].r#type.name, Some("Vessel".to_string()));
+        assert!(content.classes.contains_key("Vessel"));
+        assert_eq!(content.enumerations["VesselType"].values[0].name, "Ship");
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let mut services = canned_services();
+        services.services[0].enumerations[0].values[0].value = -1;
+        assert!(matches!(convert_services(&services), Err(Error::InvalidSchema(message)) if message == "SpaceCenter.VesselType.Ship has the negative value -1"));
+
+        let mut services = canned_services();
+        services.services[0].procedures[1].parameters[0].r#type = None;
+        assert!(matches!(convert_services(&services), Err(Error::InvalidSchema(message)) if message == "parameter this has no type"));
+    }
+
+    #[test]
+    fn test_procedure_ids_unsupported() {
+        let options = crate::Options { procedure_ids: true, ..Default::default() };
+        let result = crate::generate_from_server("127.0.0.1:0", std::path::Path::new("unused"), &options);
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_read_message_limits() {
+        let mut overlong: &[u8] = &[0xff; 11];
+        assert!(matches!(read_message::<Status>(&mut overlong), Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData));
+
+        // 1 GiB announced, without the message following it
+        let mut oversized: &[u8] = &[0x80, 0x80, 0x80, 0x80, 0x04];
+        assert!(matches!(read_message::<Status>(&mut oversized), Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData));
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+/// Settings that change the flavor of the generated code
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Call procedures by their numeric service and procedure ids instead of
+    /// by name, taking the ids from the schema files. Also generates
+    /// `check_procedure_ids` to verify at startup that the server still runs
+    /// the schema the ids came from.
+    pub procedure_ids: bool,
+    /// Version of the kRPC server the schema comes from, embedded in the
+    /// generated code. Filled in from `KRPC.GetStatus` when generating from
+    /// a running server.
+    pub krpc_version: Option<String>,
+    /// Directory with templates replacing the built-in ones: `service.rs.hbs`,
+    /// `server.rs.hbs`, `class_file.rs.hbs`, `enums_file.rs.hbs` and
+    /// `partials/<name>.rs.hbs` for the `method`, `class`, `class_header`,
+    /// `property` and `enum` partials. Missing files fall back to the built-in
+    /// templates.
+    pub template_dir: Option<PathBuf>,
+    /// `krpc-gen.toml` with rules that rename, exclude or adjust single
+    /// procedures, classes and enumerations
+    pub config: Option<PathBuf>,
+    /// Only generate the procedures matching one of these patterns:
+    /// `Service`, or `Service.Name` where `Name` is a procedure or class name
+    /// and both parts can use `*` and `?`. Classes and enumerations the
+    /// selected procedures don't use are left out.
+    pub include: Vec<String>,
+    /// Leave out the procedures matching one of these patterns, in the same
+    /// form as `include`
+    pub exclude: Vec<String>,
+    /// How the rendered code is formatted before it is written
+    pub format: Format,
+    /// Name getters `name()` instead of `get_name()`. Setters stay
+    /// `set_name()`. A getter whose bare name is a keyword or is taken by a
+    /// method of the same class keeps its `get_` prefix, with a warning.
+    pub idiomatic_accessors: bool,
+    /// Code generator used for the client bindings. Server stubs are always
+    /// rendered from the templates.
+    pub backend: Backend,
+    /// What to do when two procedures of a service or class map to the same
+    /// method name
+    pub collisions: Collisions,
+    /// Whether the client bindings of a service are written as one file or
+    /// as a directory with a file per class. Server stubs are always one
+    /// file.
+    pub layout: Layout,
+    /// Put every service behind a cargo feature named after its module.
+    /// The output is then a directory with a file per service and a
+    /// `mod.rs` declaring each module under `#[cfg(feature = ...)]`; a
+    /// service's feature enables the services whose types it uses.
+    pub features: bool,
+    /// With `features`, also write the `[features]` table for `Cargo.toml`
+    /// to this file
+    pub cargo_features: Option<PathBuf>,
+    /// Generate every method as a single call to the runtime's generic
+    /// `Connection::call` (`call_by_id` with `procedure_ids`), passing the
+    /// arguments as a tuple of values implementing `encoder::KRPCEncode`
+    pub call_helper: bool,
+    /// Generate a `{Class}Snapshot` struct for every class with a field per
+    /// property getter, and a `fetch_snapshot` method reading the fields
+    /// selected in a `{Class}Fields` mask in one batch
+    pub snapshots: bool,
+    /// Derive `serde::Serialize` and `serde::Deserialize` for the snapshot
+    /// structs and the enumerations they contain
+    pub snapshot_serde: bool,
+    /// Generate a method per property, such as `throttle()`, returning a
+    /// `property::Property` of the runtime to `get`, `set` and `stream` it
+    /// instead of separate getters and setters. Properties without a
+    /// setter are `Property<'a, T, property::ReadOnly>`, which has no
+    /// `set`.
+    pub property_objects: bool,
+    /// Treat the output directory as owned by the generator: generated
+    /// service files and service directories that this run no longer writes
+    /// are deleted, as with `Builder::managed`
+    pub managed: bool,
+}
+
+/// How the client bindings of a service are split into files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Everything in one file, e.g. `space_center.rs`
+    #[default]
+    File,
+    /// A directory such as `space_center/` with a file per class, e.g.
+    /// `vessel.rs`, the enumerations in `enums.rs` and a `mod.rs` with the
+    /// service that includes and re-exports them
+    Modules,
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Layout::File),
+            "modules" => Ok(Layout::Modules),
+            _ => Err(format!("unknown layout {}, expected file or modules", s)),
+        }
+    }
+}
+
+/// How methods whose names collide are resolved. Procedures are taken in
+/// name order; the first keeps its name and every rename or skip is
+/// reported as a warning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collisions {
+    /// Append `_2`, `_3`, ... to the later methods
+    #[default]
+    Suffix,
+    /// Leave out the later methods
+    Skip,
+    /// Fail with `Error::NameCollision`
+    Error,
+}
+
+impl std::str::FromStr for Collisions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "suffix" => Ok(Collisions::Suffix),
+            "skip" => Ok(Collisions::Skip),
+            "error" => Ok(Collisions::Error),
+            _ => Err(format!("unknown collision rule {}, expected suffix, skip or error", s)),
+        }
+    }
+}
+
+/// Code generator for the client bindings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Render the handlebars templates, which can be replaced through
+    /// `template_dir`
+    #[default]
+    Handlebars,
+    /// Build the code as tokens with `quote!`, which can't produce code that
+    /// does not parse. Ignores `template_dir`.
+    Tokens,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "handlebars" => Ok(Backend::Handlebars),
+            "tokens" => Ok(Backend::Tokens),
+            _ => Err(format!("unknown backend {}, expected handlebars or tokens", s)),
+        }
+    }
+}
+
+/// Formatting applied to the rendered code. The code is parsed in every mode
+/// except `None`, so templates producing invalid Rust are reported instead
+/// of written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Pretty print with `prettyplease`. Comments other than doc comments
+    /// and the leading file comment are dropped.
+    #[default]
+    PrettyPlease,
+    /// Run the external `rustfmt`, which must be on the `PATH`
+    Rustfmt,
+    /// Write the template output as it is
+    None,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prettyplease" => Ok(Format::PrettyPlease),
+            "rustfmt" => Ok(Format::Rustfmt),
+            "none" => Ok(Format::None),
+            _ => Err(format!("unknown format {}, expected prettyplease, rustfmt or none", s)),
+        }
+    }
+}
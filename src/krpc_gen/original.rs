@@ -1,93 +1,287 @@
 use std::fs::File;
 use std::io::BufReader;
 use std::collections::HashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use base64::Engine;
+
+use crate::error::Error;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Content {
-    id: u64,
+    pub id: u64,
     pub procedures: HashMap<String, Procedure>,
     pub classes: HashMap<String, Class>,
     pub enumerations: HashMap<String, Enumeration>,
+    #[serde(default)]
+    pub documentation: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Class {
-    // documentation: String,
+    #[serde(default)]
+    pub documentation: String,
+    /// Extra derives from the configuration file
+    #[serde(skip)]
+    pub derives: Vec<String>,
+    /// Extra attributes from the configuration file
+    #[serde(skip)]
+    pub attributes: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Enumeration {
-    // documentation: String,
+    #[serde(default)]
+    pub documentation: String,
     pub values: Vec<EnumerationValue>,
+    /// Extra derives from the configuration file
+    #[serde(skip)]
+    pub derives: Vec<String>,
+    /// Extra attributes from the configuration file
+    #[serde(skip)]
+    pub attributes: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct EnumerationValue {
     pub name: String,
     pub value: u64,
-    // documentation: String,
+    #[serde(default)]
+    pub documentation: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Procedure {
     pub id: u64,
     pub parameters: Vec<Parameter>,
-    game_scenes: Option<Vec<GameScene>>,
-    pub return_type: Option<ReturnType>,
-    return_is_nullable: Option<bool>,
-    // documentation: String,
+    #[allow(dead_code)]
+    pub game_scenes: Option<Vec<GameScene>>,
+    pub return_type: Option<Type>,
+    #[allow(dead_code)]
+    pub return_is_nullable: Option<bool>,
+    #[serde(default)]
+    pub documentation: String,
+    /// Left out of the generated code by the configuration file. Still part
+    /// of the schema hash, which covers the whole service.
+    #[serde(skip)]
+    pub excluded: bool,
+    /// Method name set in the configuration file
+    #[serde(skip)]
+    pub rename: Option<String>,
+    /// Extra attributes from the configuration file
+    #[serde(skip)]
+    pub attributes: Vec<String>,
+    /// Rust type returned instead of `return_type`, converted with `Into`
+    #[serde(skip)]
+    pub return_type_override: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Parameter {
     pub name: String,
     pub r#type: Type,
+    /// Protobuf encoded value the server uses when the argument is omitted
+    #[serde(default, deserialize_with = "deserialize_base64")]
+    pub default_value: Option<Vec<u8>>,
+    #[serde(default)]
+    pub nullable: bool,
+    /// Rust type taken instead of `type`, converted with `Into`
+    #[serde(skip)]
+    pub type_override: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct Type {
-    pub code: Code,
-    service: Option<String>,
-    pub name: Option<String>,
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    value
+        .map(|value| base64::engine::general_purpose::STANDARD.decode(value).map_err(serde::de::Error::custom))
+        .transpose()
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct ReturnType {
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Type {
     pub code: Code,
-    pub types: Option<Vec<Type>>,
-    service: Option<String>,
+    #[allow(dead_code)]
+    pub service: Option<String>,
     pub name: Option<String>,
+    pub types: Option<Vec<Type>>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-#[serde(rename_all = "UPPERCASE")]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Code {
-    String,
-    Bool,
-    Float,
+    #[default]
+    None,
     Double,
+    Float,
     Sint32,
+    Sint64,
     Uint32,
+    Uint64,
+    Bool,
+    String,
+    Bytes,
+    Class,
     Enumeration,
+    Event,
+    ProcedureCall,
+    Stream,
+    Status,
+    Services,
+    Tuple,
     List,
-    Dictionary,
     Set,
-    Tuple,
-    Class,
+    Dictionary,
+}
+
+impl Code {
+    /// Numeric value of the code in the kRPC protobuf schema
+    pub fn type_code(&self) -> i32 {
+        match self {
+            Code::None => 0,
+            Code::Double => 1,
+            Code::Float => 2,
+            Code::Sint32 => 3,
+            Code::Sint64 => 4,
+            Code::Uint32 => 5,
+            Code::Uint64 => 6,
+            Code::Bool => 7,
+            Code::String => 8,
+            Code::Bytes => 9,
+            Code::Class => 100,
+            Code::Enumeration => 101,
+            Code::Event => 200,
+            Code::ProcedureCall => 201,
+            Code::Stream => 202,
+            Code::Status => 203,
+            Code::Services => 204,
+            Code::Tuple => 300,
+            Code::List => 301,
+            Code::Set => 302,
+            Code::Dictionary => 303,
+        }
+    }
+
+    /// Name of the code in the JSON schema files, e.g. `PROCEDURE_CALL`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Code::None => "NONE",
+            Code::Double => "DOUBLE",
+            Code::Float => "FLOAT",
+            Code::Sint32 => "SINT32",
+            Code::Sint64 => "SINT64",
+            Code::Uint32 => "UINT32",
+            Code::Uint64 => "UINT64",
+            Code::Bool => "BOOL",
+            Code::String => "STRING",
+            Code::Bytes => "BYTES",
+            Code::Class => "CLASS",
+            Code::Enumeration => "ENUMERATION",
+            Code::Event => "EVENT",
+            Code::ProcedureCall => "PROCEDURE_CALL",
+            Code::Stream => "STREAM",
+            Code::Status => "STATUS",
+            Code::Services => "SERVICES",
+            Code::Tuple => "TUPLE",
+            Code::List => "LIST",
+            Code::Set => "SET",
+            Code::Dictionary => "DICTIONARY",
+        }
+    }
+
+    pub fn from_type_code(code: i32) -> Option<Code> {
+        let code = match code {
+            0 => Code::None,
+            1 => Code::Double,
+            2 => Code::Float,
+            3 => Code::Sint32,
+            4 => Code::Sint64,
+            5 => Code::Uint32,
+            6 => Code::Uint64,
+            7 => Code::Bool,
+            8 => Code::String,
+            9 => Code::Bytes,
+            100 => Code::Class,
+            101 => Code::Enumeration,
+            200 => Code::Event,
+            201 => Code::ProcedureCall,
+            202 => Code::Stream,
+            203 => Code::Status,
+            204 => Code::Services,
+            300 => Code::Tuple,
+            301 => Code::List,
+            302 => Code::Set,
+            303 => Code::Dictionary,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+impl Procedure {
+    /// Canonical form of the procedure's name and types, e.g.
+    /// `Vessel_get_Name(100:SpaceCenter.Vessel)->8`. Parameter names are left
+    /// out as renaming them does not affect callers.
+    pub fn signature(&self, name: &str) -> String {
+        let parameters: Vec<String> = self.parameters.iter()
+            .map(|parameter| type_signature(&parameter.r#type.code, &parameter.r#type.service, &parameter.r#type.name, &parameter.r#type.types))
+            .collect();
+        let return_type = match &self.return_type {
+            Some(r#type) => type_signature(&r#type.code, &r#type.service, &r#type.name, &r#type.types),
+            None => "0".to_string(),
+        };
+        format!("{}({})->{}", name, parameters.join(","), return_type)
+    }
+}
+
+fn type_signature(code: &Code, service: &Option<String>, name: &Option<String>, types: &Option<Vec<Type>>) -> String {
+    let mut result = code.type_code().to_string();
+    if let Some(name) = name {
+        result += &format!(":{}.{}", service.clone().unwrap_or_default(), name);
+    }
+    if let Some(types) = types {
+        let inner: Vec<String> = types.iter()
+            .map(|t| type_signature(&t.code, &t.service, &t.name, &t.types))
+            .collect();
+        result += &format!("({})", inner.join(","));
+    }
+    result
 }
 
 #[derive(Deserialize, Debug, Clone)]
-#[serde(rename_all = "UPPERCASE")]
-enum GameScene {
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GameScene {
+    SpaceCenter,
     Flight,
+    TrackingStation,
+    EditorVab,
+    EditorSph,
+    MissionBuilder,
 }
 
-pub fn deserialize_from_file(path: &std::path::Path) -> HashMap<String, Content> {
-    let file = File::open(path).unwrap();
+pub fn try_deserialize_from_file(path: &std::path::Path) -> Result<HashMap<String, Content>, Error> {
+    let file = File::open(path)?;
     let reader = BufReader::new(file);
 
-    let v: HashMap<String, Content> = serde_json::from_reader(reader).unwrap();
+    serde_json::from_reader(reader).map_err(|e| Error::Schema(path.to_path_buf(), e))
+}
+
+/// Load a single schema file, or every `.json` file in a directory such as
+/// `GameData/kRPC`.
+pub fn try_deserialize_from_path(path: &std::path::Path) -> Result<HashMap<String, Content>, Error> {
+    if !path.is_dir() {
+        return try_deserialize_from_file(path);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().is_some_and(|extension| extension == "json") {
+            files.push(file);
+        }
+    }
+    files.sort();
 
-    v
-}
\ No newline at end of file
+    let mut v = HashMap::new();
+    for file in files {
+        v.extend(try_deserialize_from_file(&file)?);
+    }
+    Ok(v)
+}
@@ -1,49 +1,187 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use serde::Serialize;
+use crate::original;
 
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct OutputStructure {
+    pub service_id: u64,
+    pub documentation: String,
+    pub schema_hash: String,
+    /// Names of every procedure in the schema, including excluded ones, sorted
+    pub procedure_names: Vec<String>,
     pub methods: Vec<Method>,
     pub getters_setters: Vec<Method>,
-    pub classes: HashMap<String, Class>,
+    pub properties: Vec<Property>,
+    /// Keyed by class name, so classes are generated in name order
+    pub classes: BTreeMap<String, Class>,
+    /// Sorted by name
     pub enumerations: Vec<Enumeration>,
+    pub arguments_structs: Vec<ArgumentsStruct>,
+    /// Classes and enumerations of other services used by this one, sorted,
+    /// imported from the sibling modules with `Options::features`
+    pub foreign_types: Vec<ForeignType>,
+    /// Problems found while converting the schema, such as renamed methods
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// A class or enumeration of another service, imported into the service
+/// module with `use super::{module}::{name};`
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ForeignType {
+    pub service: String,
+    /// Module of the service, its name in snake case
+    pub module: String,
+    pub name: String,
 }
 
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct Class {
     pub name: String,
+    pub documentation: String,
     pub methods: Vec<Method>,
     pub getters_setters: Vec<Method>,
+    pub properties: Vec<Property>,
     pub static_methods: Vec<Method>,
+    /// Derives added after `Debug`
+    pub derives: Vec<String>,
+    /// Attributes without the `#[...]`
+    pub attributes: Vec<String>,
+    /// Set with `Options::snapshots` if the class has a property to read
+    pub snapshot: Option<Snapshot>,
+}
+
+/// A getter paired with the setter of the same property, if there is one
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Property {
+    /// Name of the property in snake case
+    pub name: String,
+    pub getter: Method,
+    pub setter: Option<Method>,
+    /// Generated as a method returning a `property::Property` with
+    /// `Options::property_objects`. The getter and setter are then not in
+    /// `getters_setters`.
+    pub object: bool,
+}
+
+/// `{Class}Snapshot` struct with the values of the properties of a class,
+/// read by its `fetch_snapshot` method
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Snapshot {
+    pub name: String,
+    /// Bit mask selecting the fields to read, `{Class}Fields`
+    pub mask: String,
+    pub fields: Vec<SnapshotField>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SnapshotField {
+    pub name: String,
+    /// Constant of the field in the mask, e.g. `G_FORCE`
+    pub flag: String,
+    pub bit: u32,
+    /// Getter the field is read with
+    pub getter: Method,
 }
 
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct Enumeration {
     pub name: String,
+    pub documentation: String,
     pub values: Vec<EnumerationValue>,
+    /// Derives added after the built-in ones
+    pub derives: Vec<String>,
+    /// Attributes without the `#[...]`
+    pub attributes: Vec<String>,
 }
 
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct EnumerationValue {
     pub id: u64,
     pub name: String,
+    pub documentation: String,
 }
 
 #[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Argument {
     pub position: u64,
+    pub name: String,
+    /// Defaulted argument, only sent when set in the method's arguments struct
+    pub optional: bool,
+    /// Class argument that may be null
+    pub nullable: bool,
+    pub r#type: original::Type,
+    /// Rust type taken instead of the one of `type`, converted with `Into`
+    pub type_override: Option<String>,
+    /// `encoder` function for the schema type. The built-in templates
+    /// encode `value` with `KRPCEncode` instead; kept for custom templates.
     pub encoder_function: String,
+    /// Expression encoded with `KRPCEncode::krpc_encode`
     pub value: String,
+    /// Element of the arguments tuple passed to `Connection::call`, an
+    /// `Option` of `value` for optional arguments
+    pub call_value: String,
+    pub server_type: String,
 }
 
 #[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Method {
     pub id: u64,
     pub procedure: String,
+    pub signature: String,
+    pub documentation: String,
     pub name: String,
     pub arguments_signature: String,
     pub arguments: Vec<Argument>,
     pub decoder_function: String,
+    pub return_type: Option<original::Type>,
+    /// Rust type returned instead of the one of `return_type`, converted
+    /// with `Into`
+    pub return_type_override: Option<String>,
     pub return_type_signature: String,
     pub return_value: String,
+    pub server_return_type: String,
+    pub arguments_struct: Option<ArgumentsStruct>,
+    /// Attributes without the `#[...]`
+    pub attributes: Vec<String>,
+}
+
+/// Struct holding the defaulted arguments of a method
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ArgumentsStruct {
+    pub name: String,
+    pub procedure: String,
+    pub has_lifetime: bool,
+    pub fields: Vec<ArgumentsStructField>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ArgumentsStructField {
+    pub name: String,
+    pub rust_type: String,
+    pub r#type: original::Type,
+    pub type_override: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ServerStructure {
+    pub procedures: Vec<ServerProcedure>,
+    pub classes: Vec<String>,
+    pub enumerations: Vec<Enumeration>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ServerProcedure {
+    pub procedure: String,
+    pub name: String,
+    pub arguments: Vec<ServerArgument>,
+    pub return_type: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ServerArgument {
+    pub position: u64,
+    pub name: String,
+    pub rust_type: String,
+    pub optional: bool,
 }
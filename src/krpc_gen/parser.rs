@@ -1,8 +1,19 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use regex::Regex;
 use convert_case::{Case, Casing};
 use crate::original;
 use crate::output;
+use crate::error::Error;
+use crate::options::{Collisions, Options};
+
+/// Methods generated on every service besides its procedures
+const SERVICE_METHODS: &[&str] = &["new", "server_schema", "check_schema", "check_procedure_ids"];
+
+/// Methods generated on every class with `Options::snapshots`
+const SNAPSHOT_METHODS: &[&str] = &["fetch_snapshot"];
+
+/// Most fields of a snapshot, one per bit of its `u64` mask
+const SNAPSHOT_FIELDS: usize = 64;
 
 trait ParsedMethod {
     fn original_procedure_name(&self) -> String;
@@ -34,7 +45,7 @@ impl ParsedMethod for ServiceProperty {
         self.procedure.clone()
     }
     fn function_name(&self) -> String {
-        self.prefix.clone() + self.name.to_case(Case::Snake).as_str()
+        self.prefix.clone() + "_" + self.name.to_case(Case::Snake).as_str()
     }
 }
 
@@ -65,7 +76,7 @@ impl ParsedMethod for ClassProperty {
         self.procedure.clone()
     }
     fn function_name(&self) -> String {
-        self.prefix.clone() + self.property.to_case(Case::Snake).as_str()
+        self.prefix.clone() + "_" + self.property.to_case(Case::Snake).as_str()
     }
 }
 
@@ -90,24 +101,24 @@ fn get_procedure_type(procedure_name: &str) -> ProcedureType {
             "get" => {
                 return ProcedureType::ClassPropertyGetter(ClassProperty {
                     procedure: procedure_name.to_string(),
-                    class: (&cap[1]).to_string(),
-                    property: (&cap[3]).to_string(),
-                    prefix: "get_".to_string(),
+                    class: cap[1].to_string(),
+                    property: cap[3].to_string(),
+                    prefix: "get".to_string(),
                 });
             },
             "set" => {
                 return ProcedureType::ClassPropertySetter(ClassProperty {
                     procedure: procedure_name.to_string(),
-                    class: (&cap[1]).to_string(),
-                    property: (&cap[3]).to_string(),
-                    prefix: "set_".to_string(),
+                    class: cap[1].to_string(),
+                    property: cap[3].to_string(),
+                    prefix: "set".to_string(),
                 });
             },
             "static" => {
                 return ProcedureType::StaticClassMethod(ClassMethod {
                     procedure: procedure_name.to_string(),
-                    class: (&cap[1]).to_string(),
-                    method: (&cap[3]).to_string(),
+                    class: cap[1].to_string(),
+                    method: cap[3].to_string(),
                 });
             },
             _ => {
@@ -122,76 +133,81 @@ fn get_procedure_type(procedure_name: &str) -> ProcedureType {
             "get" => {
                 return ProcedureType::PropertyGetter(ServiceProperty {
                     procedure: procedure_name.to_string(),
-                    name: (&cap[2]).to_string(),
-                    prefix: "get_".to_string(),
+                    name: cap[2].to_string(),
+                    prefix: "get".to_string(),
                 })
             },
             "set" => {
                 return ProcedureType::PropertySetter(ServiceProperty {
                     procedure: procedure_name.to_string(),
-                    name: (&cap[2]).to_string(),
-                    prefix: "set_".to_string(),
+                    name: cap[2].to_string(),
+                    prefix: "set".to_string(),
                 })
             },
             _ => {
                 return ProcedureType::ClassMethod(ClassMethod {
                     procedure: procedure_name.to_string(),
-                    class: (&cap[1]).to_string(),
-                    method: (&cap[2]).to_string(),
+                    class: cap[1].to_string(),
+                    method: cap[2].to_string(),
                 })
             }
         }
     }
-    return ProcedureType::Standard(StandardMethod {
+    ProcedureType::Standard(StandardMethod {
         procedure: procedure_name.to_string(),
         name: procedure_name.to_string(),
     })
 }
 
-pub fn create_output_structure(input_structure: &original::Content) -> output::OutputStructure {
+pub fn create_output_structure(input_structure: &original::Content, options: &Options) -> Result<output::OutputStructure, Error> {
     let mut service_methods = Vec::<output::Method>::new();
     let mut service_getters_setters = Vec::<output::Method>::new();
-    let mut classes = HashMap::<String, output::Class>::new();
+    let mut classes = BTreeMap::<String, output::Class>::new();
     let mut enumerations = Vec::<output::Enumeration>::new();
     
     // create maps for all classes
     for class in &input_structure.classes {
         classes.insert(class.0.clone(), output::Class {
             name: class.0.clone(),
+            documentation: class.1.documentation.clone(),
             methods: vec![],
             getters_setters: vec![],
+            properties: vec![],
             static_methods: vec![],
+            derives: class.1.derives.clone(),
+            attributes: class.1.attributes.clone(),
+            snapshot: None,
         });
     }    
 
     // parse procedures
-    for proc in &input_structure.procedures {
+    for proc in input_structure.procedures.iter().filter(|proc| !proc.1.excluded) {
         let procedure_type = get_procedure_type(proc.0);
         match &procedure_type {
             ProcedureType::Standard(x) => {
-                service_methods.push(convert_method(x, &proc.1, false));
+                service_methods.push(convert_method(x, proc.1, false));
             },
             ProcedureType::PropertyGetter(x) => {
-                service_getters_setters.push(convert_method(x, &proc.1, false));
+                service_getters_setters.push(convert_method(x, proc.1, false));
             },
             ProcedureType::PropertySetter(x) => {
-                service_getters_setters.push(convert_method(x, &proc.1, false));
+                service_getters_setters.push(convert_method(x, proc.1, false));
             },
             ProcedureType::ClassMethod(x) => {
                 // add_class_if_nonexistent(&mut classes, &x.class);
-                classes.get_mut(&x.class).unwrap().methods.push(convert_method(x, &proc.1, false));
+                classes.get_mut(&x.class).unwrap().methods.push(convert_method(x, proc.1, false));
             },
             ProcedureType::ClassPropertyGetter(x) => {
                 // add_class_if_nonexistent(&mut classes, &x.class);
-                classes.get_mut(&x.class).unwrap().getters_setters.push(convert_method(x, &proc.1, false));
+                classes.get_mut(&x.class).unwrap().getters_setters.push(convert_method(x, proc.1, false));
             },
             ProcedureType::ClassPropertySetter(x) => {
                 // add_class_if_nonexistent(&mut classes, &x.class);
-                classes.get_mut(&x.class).unwrap().getters_setters.push(convert_method(x, &proc.1, false));
+                classes.get_mut(&x.class).unwrap().getters_setters.push(convert_method(x, proc.1, false));
             },
             ProcedureType::StaticClassMethod(x) => {
                 // add_class_if_nonexistent(&mut classes, &x.class);
-                classes.get_mut(&x.class).unwrap().static_methods.push(convert_method(x, &proc.1, true));
+                classes.get_mut(&x.class).unwrap().static_methods.push(convert_method(x, proc.1, true));
             },
             ProcedureType::Unknown => {}
         }
@@ -203,120 +219,541 @@ pub fn create_output_structure(input_structure: &original::Content) -> output::O
             .map(|v| output::EnumerationValue {
                 id: v.value,
                 name: v.name.clone(),
+                documentation: v.documentation.clone(),
             })
             .collect();
         let enumeration = output::Enumeration {
             name: e.0.to_string(),
+            documentation: e.1.documentation.clone(),
             values: enum_values,
+            derives: e.1.derives.clone(),
+            attributes: e.1.attributes.clone(),
         };
         enumerations.push(enumeration);
     }
     
     // Sort lists
+    enumerations.sort_by(|a, b| a.name.cmp(&b.name));
     service_methods.sort();
     service_getters_setters.sort();
     
-    for (_, class) in &mut classes {
+    for class in classes.values_mut() {
         class.methods.sort();
         class.getters_setters.sort();
         class.static_methods.sort();
     }
+
+    let mut warnings = Vec::new();
+    if options.idiomatic_accessors {
+        let reserved = SERVICE_METHODS.iter().map(|name| name.to_string());
+        let taken: Vec<String> = service_methods.iter().map(|method| method.name.clone()).chain(reserved).collect();
+        drop_getter_prefixes(&mut service_getters_setters, taken, &mut warnings);
+        for class in classes.values_mut() {
+            let taken = class.methods.iter().chain(&class.static_methods).map(|method| method.name.clone()).collect();
+            drop_getter_prefixes(&mut class.getters_setters, taken, &mut warnings);
+        }
+    }
+
+    // Names from the configuration file are used as they are
+    let renamed = service_methods.iter_mut()
+        .chain(&mut service_getters_setters)
+        .chain(classes.values_mut().flat_map(|class| class.methods.iter_mut().chain(&mut class.getters_setters).chain(&mut class.static_methods)));
+    for method in renamed {
+        if let Some(rename) = &input_structure.procedures[&method.procedure].rename {
+            method.name = rename.clone();
+        }
+    }
+
+    resolve_collisions(&mut [&mut service_methods, &mut service_getters_setters], SERVICE_METHODS, options.collisions, &mut warnings)?;
+    let class_methods = if options.snapshots { SNAPSHOT_METHODS } else { &[] };
+    for class in classes.values_mut() {
+        resolve_collisions(&mut [&mut class.methods, &mut class.getters_setters, &mut class.static_methods], class_methods, options.collisions, &mut warnings)?;
+    }
+
+    if options.snapshots {
+        for class in classes.values_mut() {
+            class.snapshot = snapshot(class, &mut warnings);
+        }
+    }
+
+    let mut service_properties = properties(&mut service_getters_setters, options.property_objects);
+    let taken: Vec<&str> = SERVICE_METHODS.iter().copied()
+        .chain(service_methods.iter().chain(&service_getters_setters).map(|method| method.name.as_str()))
+        .collect();
+    name_property_objects(&mut service_properties, &taken, options.collisions, &mut warnings)?;
+    for class in classes.values_mut() {
+        class.properties = properties(&mut class.getters_setters, options.property_objects);
+        let taken: Vec<&str> = class_methods.iter().copied()
+            .chain(class.methods.iter().chain(&class.getters_setters).chain(&class.static_methods).map(|method| method.name.as_str()))
+            .collect();
+        name_property_objects(&mut class.properties, &taken, options.collisions, &mut warnings)?;
+    }
     
-    output::OutputStructure {
+    let mut arguments_structs: Vec<output::ArgumentsStruct> = service_methods.iter()
+        .chain(&service_getters_setters)
+        .chain(classes.values().flat_map(|class| class.methods.iter().chain(&class.getters_setters).chain(&class.static_methods)))
+        .filter_map(|method| method.arguments_struct.clone())
+        .collect();
+    arguments_structs.sort();
+
+    let signatures: Vec<String> = input_structure.procedures.iter()
+        .map(|(name, procedure)| procedure.signature(name))
+        .collect();
+    let mut procedure_names: Vec<String> = input_structure.procedures.keys().cloned().collect();
+    procedure_names.sort();
+
+    Ok(output::OutputStructure {
+        service_id: input_structure.id,
+        documentation: input_structure.documentation.clone(),
+        schema_hash: format!("0x{:016x}", schema_hash(signatures)),
+        procedure_names,
         methods: service_methods,
         getters_setters: service_getters_setters,
-        classes: classes,
-        enumerations: enumerations,
+        properties: service_properties,
+        classes,
+        enumerations,
+        arguments_structs,
+        foreign_types: Vec::new(),
+        warnings,
+    })
+}
+
+/// Name getters `name()` instead of `get_name()`. A getter keeps its prefix
+/// when the bare name is a Rust keyword or is already taken by another method
+/// of the same type, with a warning.
+fn drop_getter_prefixes(getters_setters: &mut [output::Method], mut taken: Vec<String>, warnings: &mut Vec<String>) {
+    taken.extend(getters_setters.iter().map(|method| method.name.clone()));
+    for method in getters_setters.iter_mut() {
+        let is_getter = matches!(get_procedure_type(&method.procedure), ProcedureType::PropertyGetter(_) | ProcedureType::ClassPropertyGetter(_));
+        let bare_name = match method.name.strip_prefix("get_") {
+            Some(bare_name) if is_getter => bare_name.to_string(),
+            _ => continue,
+        };
+        if syn::parse_str::<syn::Ident>(&bare_name).is_err() {
+            warnings.push(format!("{}: `{}` is a keyword, keeping `{}`", method.procedure, bare_name, method.name));
+        } else if taken.contains(&bare_name) {
+            warnings.push(format!("{}: `{}` is already used by another method, keeping `{}`", method.procedure, bare_name, method.name));
+        } else {
+            taken.push(bare_name.clone());
+            method.name = bare_name;
+        }
     }
 }
 
+/// Make the method names of one `impl` block unique. Procedures are visited
+/// in name order, so the first to use a name keeps it whatever the rule.
+fn resolve_collisions(lists: &mut [&mut Vec<output::Method>], reserved: &[&str], rule: Collisions, warnings: &mut Vec<String>) -> Result<(), Error> {
+    let mut order: Vec<(String, usize, usize)> = lists.iter().enumerate()
+        .flat_map(|(list, methods)| methods.iter().enumerate().map(move |(index, method)| (method.procedure.clone(), list, index)))
+        .collect();
+    order.sort();
+
+    let mut taken: Vec<String> = reserved.iter().map(|name| name.to_string()).collect();
+    let mut skipped = Vec::new();
+    for (procedure, list, index) in order {
+        let method = &mut lists[list][index];
+        if !taken.contains(&method.name) {
+            taken.push(method.name.clone());
+            continue;
+        }
+        match rule {
+            Collisions::Suffix => {
+                let name = (2..).map(|n| format!("{}_{}", method.name, n))
+                    .find(|name| !taken.contains(name))
+                    .unwrap();
+                warnings.push(format!("{}: `{}` is already used by another method, renamed to `{}`", procedure, method.name, name));
+                taken.push(name.clone());
+                method.name = name;
+            },
+            Collisions::Skip => {
+                warnings.push(format!("{}: `{}` is already used by another method, skipped", procedure, method.name));
+                skipped.push((list, index));
+            },
+            Collisions::Error => return Err(Error::NameCollision { procedure, name: method.name.clone() }),
+        }
+    }
+
+    // Remove from the back so the remaining indices stay valid
+    skipped.sort();
+    for (list, index) in skipped.into_iter().rev() {
+        lists[list].remove(index);
+    }
+    Ok(())
+}
+
+/// Pair every getter with the setter of the same property. With
+/// `property_objects`, the getters and setters of the properties that become
+/// `Property` objects are taken out of `getters_setters`.
+fn properties(getters_setters: &mut Vec<output::Method>, property_objects: bool) -> Vec<output::Property> {
+    let mut properties = Vec::new();
+    for getter in getters_setters.iter() {
+        let (name, setter_procedure) = match get_procedure_type(&getter.procedure) {
+            ProcedureType::PropertyGetter(property) => (property.name.clone(), format!("set_{}", property.name)),
+            ProcedureType::ClassPropertyGetter(property) => (property.property.clone(), format!("{}_set_{}", property.class, property.property)),
+            _ => continue,
+        };
+        let setter = getters_setters.iter().find(|method| method.procedure == setter_procedure).cloned();
+        let name = name.to_case(Case::Snake);
+        properties.push(output::Property {
+            name: raw_identifier(name),
+            object: property_objects && is_property_object(getter, setter.as_ref()),
+            getter: getter.clone(),
+            setter,
+        });
+    }
+
+    let objects: Vec<&str> = property_object_methods(&properties).map(|method| method.procedure.as_str()).collect();
+    getters_setters.retain(|method| !objects.contains(&method.procedure.as_str()));
+    properties
+}
+
+/// Whether a property can be read and written through `Property<T>`: the
+/// getter and setter take nothing but the object and the value, and their
+/// types aren't overridden, as `Property` doesn't convert them
+fn is_property_object(getter: &output::Method, setter: Option<&output::Method>) -> bool {
+    let plain_getter = getter.arguments.iter().all(|argument| argument.name == "this")
+        && getter.return_type.is_some()
+        && getter.return_type_override.is_none();
+    let plain_setter = setter.is_none_or(|setter| {
+        let values: Vec<&output::Argument> = setter.arguments.iter().filter(|argument| argument.name != "this").collect();
+        values.len() == 1 && !values[0].optional && !values[0].nullable && values[0].type_override.is_none()
+    });
+    plain_getter && plain_setter
+}
+
+/// Resolve collisions of the methods returning `Property` objects with the
+/// other methods of the same `impl` block, which keep their names
+fn name_property_objects(properties: &mut Vec<output::Property>, taken: &[&str], rule: Collisions, warnings: &mut Vec<String>) -> Result<(), Error> {
+    let mut accessors: Vec<output::Method> = properties.iter()
+        .filter(|property| property.object)
+        .map(|property| output::Method { name: property.name.clone(), ..property.getter.clone() })
+        .collect();
+    resolve_collisions(&mut [&mut accessors], taken, rule, warnings)?;
+    // Properties whose accessor was skipped are left out entirely
+    properties.retain_mut(|property| {
+        if !property.object {
+            return true;
+        }
+        match accessors.iter().find(|accessor| accessor.procedure == property.getter.procedure) {
+            Some(accessor) => {
+                property.name = accessor.name.clone();
+                true
+            },
+            None => false,
+        }
+    });
+    Ok(())
+}
+
+/// Snapshot of the properties of a class that take only the object itself
+/// and return plain data. Objects are left out as they borrow the
+/// connection, which a snapshot shouldn't.
+fn snapshot(class: &output::Class, warnings: &mut Vec<String>) -> Option<output::Snapshot> {
+    let mut fields = Vec::new();
+    for getter in &class.getters_setters {
+        let property = match get_procedure_type(&getter.procedure) {
+            ProcedureType::ClassPropertyGetter(property) => property.property,
+            _ => continue,
+        };
+        if getter.arguments.len() != 1 || !getter.return_type.as_ref().is_some_and(is_plain_data) {
+            continue;
+        }
+        if fields.len() == SNAPSHOT_FIELDS {
+            warnings.push(format!("{}: {} already has {} snapshot fields, left out of the snapshot", getter.procedure, class.name, SNAPSHOT_FIELDS));
+            continue;
+        }
+        let name = property.to_case(Case::Snake);
+        fields.push(output::SnapshotField {
+            name: raw_identifier(name),
+            flag: property.to_case(Case::UpperSnake),
+            bit: fields.len() as u32,
+            getter: getter.clone(),
+        });
+    }
+    if fields.is_empty() {
+        return None;
+    }
+    Some(output::Snapshot {
+        name: format!("{}Snapshot", class.name),
+        mask: format!("{}Fields", class.name),
+        fields,
+    })
+}
+
+/// Whether values of the type own all their data
+fn is_plain_data(r#type: &original::Type) -> bool {
+    match r#type.code {
+        original::Code::Class |
+        original::Code::Event |
+        original::Code::ProcedureCall |
+        original::Code::Stream |
+        original::Code::Status |
+        original::Code::Services => false,
+        _ => r#type.types.iter().flatten().all(is_plain_data),
+    }
+}
+
+/// FNV-1a hash of the sorted procedure signatures of a service. The generated
+/// `check_schema` computes the same hash from `KRPC.GetServices`.
+fn schema_hash(mut signatures: Vec<String>) -> u64 {
+    signatures.sort();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in signatures.join("\n").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 fn convert_method(property: &impl ParsedMethod, procedure: &original::Procedure, is_static: bool) -> output::Method {
+    let arguments_struct = arguments_struct(&property.original_procedure_name(), procedure);
     output::Method {
         id: procedure.id,
         procedure: property.original_procedure_name(),
-        name: property.function_name(),
-        arguments_signature: arguments_signature(&procedure, is_static),
-        arguments: convert_arguments(&procedure),
-        decoder_function: decoder_function(&procedure),
-        return_type_signature: return_type_signature(&procedure),
-        return_value: return_value(&procedure, is_static),
+        signature: procedure.signature(&property.original_procedure_name()),
+        documentation: procedure.documentation.clone(),
+        name: raw_identifier(property.function_name()),
+        arguments_signature: arguments_signature(procedure, is_static, &arguments_struct),
+        arguments: convert_arguments(procedure),
+        decoder_function: decoder_function(procedure),
+        return_type: procedure.return_type.clone(),
+        return_type_override: procedure.return_type_override.clone(),
+        return_type_signature: return_type_signature(procedure),
+        return_value: return_value(procedure, is_static),
+        server_return_type: server_return_type(procedure),
+        arguments_struct,
+        attributes: procedure.attributes.clone(),
     }
 }
 
-fn arguments_signature(procedure: &original::Procedure, is_static: bool) -> String {
+pub fn create_server_structure(output_structure: &output::OutputStructure) -> output::ServerStructure {
+    let mut methods: Vec<&output::Method> = Vec::new();
+    methods.extend(&output_structure.methods);
+    methods.extend(&output_structure.getters_setters);
+    methods.extend(property_object_methods(&output_structure.properties));
+    for class in output_structure.classes.values() {
+        methods.extend(&class.methods);
+        methods.extend(&class.getters_setters);
+        methods.extend(property_object_methods(&class.properties));
+        methods.extend(&class.static_methods);
+    }
+
+    let mut procedures: Vec<output::ServerProcedure> = methods.iter()
+        .map(|method| output::ServerProcedure {
+            procedure: method.procedure.clone(),
+            name: raw_identifier(method.procedure.to_case(Case::Snake)),
+            arguments: method.arguments.iter()
+                .map(|argument| output::ServerArgument {
+                    position: argument.position,
+                    name: argument.name.clone(),
+                    rust_type: if argument.optional {
+                        format!("Option<{}>", argument.server_type)
+                    } else {
+                        argument.server_type.clone()
+                    },
+                    optional: argument.optional,
+                })
+                .collect(),
+            return_type: method.server_return_type.clone(),
+        })
+        .collect();
+    procedures.sort();
+
+    let classes: Vec<String> = output_structure.classes.keys().cloned().collect();
+
+    output::ServerStructure {
+        procedures,
+        classes,
+        enumerations: output_structure.enumerations.clone(),
+    }
+}
+
+/// `name` as an identifier, as a raw identifier if it is a Rust keyword.
+/// Names go into the output structure escaped this way, and both backends
+/// print them as they are.
+pub fn raw_identifier(name: String) -> String {
+    if syn::parse_str::<syn::Ident>(&name).is_ok() {
+        name
+    } else {
+        format!("r#{}", name)
+    }
+}
+
+/// Getters and setters of the properties generated as `Property` objects
+pub fn property_object_methods(properties: &[output::Property]) -> impl Iterator<Item = &output::Method> {
+    properties.iter()
+        .filter(|property| property.object)
+        .flat_map(|property| std::iter::once(&property.getter).chain(&property.setter))
+}
+
+fn arguments_signature(procedure: &original::Procedure, is_static: bool, arguments_struct: &Option<output::ArgumentsStruct>) -> String {
     let first_argument = if is_static { "conn: &'a Connection" } else { "&'a self" }.to_string();
-    let arguments: Vec<String> = procedure.parameters.iter()
-        .filter(|param| param.name != "this")
+    let mut arguments: Vec<String> = procedure.parameters.iter()
+        .filter(|param| param.name != "this" && param.default_value.is_none())
         .map(|param|
-            param.name.to_case(Case::Snake) + ": " +
+            raw_identifier(param.name.to_case(Case::Snake)) + ": " +
             argument_type(param).as_str()
         )
         .collect();
+    if let Some(arguments_struct) = arguments_struct {
+        let lifetime = if arguments_struct.has_lifetime { "<'_>" } else { "" };
+        arguments.push(format!("args: {}{}", arguments_struct.name, lifetime));
+    }
 
     let arguments = [Vec::from([first_argument]), arguments].concat();
     arguments.join(", ")
 }
 
+/// Struct for the defaulted parameters of a procedure, so the caller only
+/// sets the ones it needs and the server applies its defaults to the rest
+fn arguments_struct(procedure_name: &str, procedure: &original::Procedure) -> Option<output::ArgumentsStruct> {
+    let fields: Vec<output::ArgumentsStructField> = procedure.parameters.iter()
+        .filter(|param| param.default_value.is_some())
+        .map(|param| {
+            let rust_type = match (&param.type_override, &param.r#type.code) {
+                (Some(type_override), _) => type_override.clone(),
+                (None, original::Code::Class) => format!("&'b {}", rust_type(&param.r#type, "'b")),
+                (None, _) => rust_type(&param.r#type, "'b"),
+            };
+            output::ArgumentsStructField {
+                name: raw_identifier(param.name.to_case(Case::Snake)),
+                rust_type: format!("Option<{}>", rust_type),
+                r#type: param.r#type.clone(),
+                type_override: param.type_override.clone(),
+            }
+        })
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    Some(output::ArgumentsStruct {
+        name: procedure_name.replace("_static_", "_").to_case(Case::Pascal) + "Args",
+        procedure: procedure_name.to_string(),
+        has_lifetime: fields.iter().any(|field| field.rust_type.contains("'b")),
+        fields,
+    })
+}
+
 fn argument_type(parameter: &original::Parameter) -> String {
+    if let Some(type_override) = &parameter.type_override {
+        return type_override.clone();
+    }
     match parameter.r#type.code {
-        original::Code::String => "String".to_string(),
-        original::Code::Bool => "bool".to_string(),
-        original::Code::Float => "f32".to_string(),
-        original::Code::Double => "f64".to_string(),
-        original::Code::Sint32 => "i32".to_string(),
-        original::Code::Uint32 => "u32".to_string(),
-        original::Code::Enumeration => parameter.r#type.name.clone().unwrap(),
-        original::Code::List => "(/*list*/)".to_string(),
-        original::Code::Dictionary => "(/*dict*/)".to_string(),
-        original::Code::Set => "(/*set*/)".to_string(),
-        original::Code::Tuple => "(/*tuple*/)".to_string(),
+        original::Code::Class if parameter.nullable => {
+            "Option<&".to_string() + parameter.r#type.name.clone().unwrap().as_str() + "<'_>>"
+        },
         original::Code::Class => {
             "&".to_string() + parameter.r#type.name.clone().unwrap().as_str() + "<'_>"
         },
+        _ => rust_type(&parameter.r#type, "'_"),
     }
 }
 
-fn convert_arguments(procedure: &original::Procedure) -> Vec<output::Argument> {
-    let mut arguments = Vec::new();
-    let mut position = 0;
-    for p in &procedure.parameters {
-        arguments.push(convert_single_argument(&p, position));
-        position += 1;
+/// Rust type of a value of the given kRPC type, with `lifetime` used for the
+/// connection of classes
+pub fn rust_type(r#type: &original::Type, lifetime: &str) -> String {
+    let inner: Vec<String> = r#type.types.iter().flatten()
+        .map(|t| rust_type(t, lifetime))
+        .collect();
+    match r#type.code {
+        original::Code::None => "()".to_string(),
+        original::Code::Double => "f64".to_string(),
+        original::Code::Float => "f32".to_string(),
+        original::Code::Sint32 => "i32".to_string(),
+        original::Code::Sint64 => "i64".to_string(),
+        original::Code::Uint32 => "u32".to_string(),
+        original::Code::Uint64 => "u64".to_string(),
+        original::Code::Bool => "bool".to_string(),
+        original::Code::String => "String".to_string(),
+        original::Code::Bytes => "Vec<u8>".to_string(),
+        original::Code::Class => format!("{}<{}>", r#type.name.clone().unwrap(), lifetime),
+        original::Code::Enumeration => r#type.name.clone().unwrap(),
+        original::Code::Event => "schema::Event".to_string(),
+        original::Code::ProcedureCall => "schema::ProcedureCall".to_string(),
+        original::Code::Stream => "schema::Stream".to_string(),
+        original::Code::Status => "schema::Status".to_string(),
+        original::Code::Services => "schema::Services".to_string(),
+        original::Code::Tuple if inner.len() == 1 => format!("({},)", inner[0]),
+        original::Code::Tuple => format!("({})", inner.join(", ")),
+        original::Code::List => format!("Vec<{}>", inner.join(", ")),
+        original::Code::Set => format!("HashSet<{}>", inner.join(", ")),
+        original::Code::Dictionary => format!("HashMap<{}>", inner.join(", ")),
     }
-    arguments
+}
+
+fn convert_arguments(procedure: &original::Procedure) -> Vec<output::Argument> {
+    procedure.parameters.iter()
+        .enumerate()
+        .map(|(position, p)| convert_single_argument(p, position as u64))
+        .collect()
 }
 
 fn convert_single_argument(parameter: &original::Parameter, position: u64) -> output::Argument {
     if parameter.name == "this" {
         return output::Argument {
             position: 0,
+            name: parameter.name.clone(),
+            optional: false,
+            nullable: false,
+            r#type: parameter.r#type.clone(),
+            type_override: None,
             encoder_function: "encode_u64".to_string(),
-            value: "self.id".to_string(),
+            value: "self".to_string(),
+            call_value: "self".to_string(),
+            server_type: server_type(&parameter.r#type),
         };
     }
     let encoder_function = match parameter.r#type.code {
-        original::Code::String => "encode_string".to_string(),
-        original::Code::Bool => "encode_bool".to_string(),
-        original::Code::Float => "encode_float".to_string(),
+        original::Code::None => "encode_none".to_string(),
         original::Code::Double => "encode_double".to_string(),
+        original::Code::Float => "encode_float".to_string(),
         original::Code::Sint32 => "encode_sint32".to_string(),
+        original::Code::Sint64 => "encode_sint64".to_string(),
         original::Code::Uint32 => "encode_uint32".to_string(),
-        original::Code::Enumeration => "encode_u64".to_string(),
+        original::Code::Uint64 => "encode_uint64".to_string(),
+        original::Code::Bool => "encode_bool".to_string(),
+        original::Code::String => "encode_string".to_string(),
+        original::Code::Bytes => "encode_bytes".to_string(),
+        original::Code::Class => "encode_u64".to_string(),
+        original::Code::Enumeration => "encode_sint32".to_string(),
+        original::Code::Event => "encode_event".to_string(),
+        original::Code::ProcedureCall => "encode_procedure_call".to_string(),
+        original::Code::Stream => "encode_stream".to_string(),
+        original::Code::Status => "encode_status".to_string(),
+        original::Code::Services => "encode_services".to_string(),
+        original::Code::Tuple => "encode_tuple".to_string(),
         original::Code::List => "encode_list".to_string(),
-        original::Code::Dictionary => "encode_dictionary".to_string(),
         original::Code::Set => "encode_set".to_string(),
-        original::Code::Tuple => "encode_tuple".to_string(),
-        original::Code::Class => "encode_u64".to_string(),
+        original::Code::Dictionary => "encode_dictionary".to_string(),
+    };
+    let name = raw_identifier(parameter.name.to_case(Case::Snake));
+    let optional = parameter.default_value.is_some();
+    // A configured type is converted into the schema's type before encoding
+    let source = match &parameter.type_override {
+        Some(_) => format!("Into::<{}>::into({})", rust_type(&parameter.r#type, "'_"), name),
+        None => name.clone(),
     };
     let value = match parameter.r#type.code {
-        original::Code::Class => parameter.name.to_case(Case::Snake) + ".id",
-        original::Code::Enumeration => parameter.name.to_case(Case::Snake) + " as u64",
-        _ => parameter.name.to_case(Case::Snake),
+        // A null object is sent as id 0
+        original::Code::Class if parameter.nullable && !optional => format!("{}.map_or(0, |{}| {}.id)", name, name, name),
+        _ => source,
+    };
+    let call_value = match optional {
+        true if value == name => format!("args.{}", name),
+        true => format!("args.{}.map(|{}| {})", name, name, value),
+        false => value.clone(),
     };
     output::Argument {
         position,
+        name,
+        optional,
+        nullable: parameter.nullable,
+        r#type: parameter.r#type.clone(),
+        type_override: parameter.type_override.clone(),
         encoder_function,
         value,
+        call_value,
+        server_type: server_type(&parameter.r#type),
     }
 }
 
@@ -324,63 +761,49 @@ fn decoder_function(procedure: &original::Procedure) -> String {
     match &procedure.return_type {
         Some(return_type) => {
             match &return_type.code {
-                original::Code::String => "decode_string".to_string(),
-                original::Code::Bool => "decode_bool".to_string(),
-                original::Code::Float => "decode_float".to_string(),
+                original::Code::None => "decode_none".to_string(),
                 original::Code::Double => "decode_double".to_string(),
+                original::Code::Float => "decode_float".to_string(),
                 original::Code::Sint32 => "decode_sint32".to_string(),
+                original::Code::Sint64 => "decode_sint64".to_string(),
                 original::Code::Uint32 => "decode_uint32".to_string(),
-                original::Code::Enumeration => "decode_enumeration".to_string(),
-                original::Code::List => {
-                    let types = (&return_type.types).clone().unwrap();
-                    let list_type_string = get_list_type(&types);
-                    format!("decode_list::<{}>", list_type_string).to_string()
-                },
-                original::Code::Dictionary => "decode_dictionary".to_string(),
-                original::Code::Set => "decode_set".to_string(),
-                original::Code::Tuple => "decode_tuple".to_string(),
+                original::Code::Uint64 => "decode_uint64".to_string(),
+                original::Code::Bool => "decode_bool".to_string(),
+                original::Code::String => "decode_string".to_string(),
+                original::Code::Bytes => "decode_bytes".to_string(),
                 original::Code::Class => "decode_class".to_string(),
+                original::Code::Enumeration => format!("decode_enumeration::<{}>", rust_type(return_type, "'_")),
+                original::Code::Event => "decode_event".to_string(),
+                original::Code::ProcedureCall => "decode_procedure_call".to_string(),
+                original::Code::Stream => "decode_stream".to_string(),
+                original::Code::Status => "decode_status".to_string(),
+                original::Code::Services => "decode_services".to_string(),
+                original::Code::Tuple => format!("decode_tuple::<{}>", rust_type(return_type, "'_")),
+                original::Code::List |
+                original::Code::Set |
+                original::Code::Dictionary => {
+                    let name = match return_type.code {
+                        original::Code::List => "decode_list",
+                        original::Code::Set => "decode_set",
+                        _ => "decode_dictionary",
+                    };
+                    let types: Vec<String> = return_type.types.iter().flatten()
+                        .map(|t| rust_type(t, "'_"))
+                        .collect();
+                    format!("{}::<{}>", name, types.join(", "))
+                },
             }
         },
         None => "decode_none".to_string()
     }
 }
 
-fn get_list_type(types: &Vec<original::Type>) -> String {
-    let list_type = types.get(0).unwrap();
-    match list_type.code {
-        original::Code::String => "String".to_string(),
-        original::Code::Class => {
-            list_type.name.clone().unwrap() + "<'_>"
-        },
-        _ => "(/*list*/)".to_string()
-    }
-}
-
 fn return_type_signature(procedure: &original::Procedure) -> String {
+    if let Some(return_type_override) = &procedure.return_type_override {
+        return return_type_override.clone();
+    }
     match &procedure.return_type {
-        Some(return_type) => {
-            match &return_type.code {
-                original::Code::String => "String".to_string(),
-                original::Code::Bool => "bool".to_string(),
-                original::Code::Float => "f32".to_string(),
-                original::Code::Double => "f64".to_string(),
-                original::Code::Sint32 => "i32".to_string(),
-                original::Code::Uint32 => "u32".to_string(),
-                original::Code::Enumeration => "(/*enum*/)".to_string(),
-                original::Code::List => {
-                    let types = (&return_type.types).clone().unwrap();
-                    let list_type_string = get_list_type(&types);
-                    format!("Vec<{}>", list_type_string).to_string()
-                },
-                original::Code::Dictionary => "(/*dict*/)".to_string(),
-                original::Code::Set => "(/*set*/)".to_string(),
-                original::Code::Tuple => "(/*tuple*/)".to_string(),
-                original::Code::Class => {
-                    return_type.name.clone().unwrap() + "<'a>"
-                },
-            }
-        },
+        Some(return_type) => rust_type(return_type, "'a"),
         None => {
             "()".to_string()
         },
@@ -388,25 +811,23 @@ fn return_type_signature(procedure: &original::Procedure) -> String {
 }
 
 fn return_value(procedure: &original::Procedure, is_static: bool) -> String {
+    let value = decoded_value(procedure, is_static);
+    match &procedure.return_type_override {
+        Some(_) => format!("{}.into()", value),
+        None => value,
+    }
+}
+
+fn decoded_value(procedure: &original::Procedure, is_static: bool) -> String {
     match &procedure.return_type {
         Some(return_type) => {
             match &return_type.code {
-                original::Code::String |
-                original::Code::Bool |
-                original::Code::Float |
-                original::Code::Double | 
-                original::Code::Sint32 |
-                original::Code::Uint32 | 
-                original::Code::Enumeration | 
-                original::Code::List |
-                original::Code::Dictionary |
-                original::Code::Set |
-                original::Code::Tuple => "return_value".to_string(),
                 original::Code::Class => {
                     format!("{}{{id: return_value, conn: {}}}",
                         return_type.name.clone().unwrap(),
                         if is_static { "&conn" } else { "&self.conn" })
                 },
+                _ => "return_value".to_string(),
             }
         },
         None => {
@@ -415,6 +836,27 @@ fn return_value(procedure: &original::Procedure, is_static: bool) -> String {
     }
 }
 
+fn server_type(r#type: &original::Type) -> String {
+    let inner: Vec<String> = r#type.types.iter().flatten().map(server_type).collect();
+    match r#type.code {
+        original::Code::Enumeration |
+        original::Code::Class => r#type.name.clone().unwrap(),
+        original::Code::Tuple if inner.len() == 1 => format!("({},)", inner[0]),
+        original::Code::Tuple => format!("({})", inner.join(", ")),
+        original::Code::List => format!("Vec<{}>", inner.join(", ")),
+        original::Code::Set => format!("HashSet<{}>", inner.join(", ")),
+        original::Code::Dictionary => format!("HashMap<{}>", inner.join(", ")),
+        _ => rust_type(r#type, "'_"),
+    }
+}
+
+fn server_return_type(procedure: &original::Procedure) -> String {
+    match &procedure.return_type {
+        Some(return_type) => server_type(return_type),
+        None => "()".to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -463,4 +905,317 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_encoded_values() {
+        let parameters: Vec<original::Parameter> = serde_json::from_str(r#"[
+            {"name": "parts", "type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]}},
+            {"name": "mode", "type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "WarpMode"}},
+            {"name": "target", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}, "nullable": true}
+        ]"#).unwrap();
+        let values: Vec<String> = parameters.iter().enumerate()
+            .map(|(position, parameter)| convert_single_argument(parameter, position as u64).value)
+            .collect();
+        assert_eq!(values, ["parts", "mode", "target.map_or(0, |target| target.id)"]);
+    }
+
+    #[test]
+    fn test_schema_hash() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "Vessel_get_Parts": {
+                    "id": 2,
+                    "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}],
+                    "return_type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]}
+                }
+            },
+            "classes": {"Vessel": {}, "Part": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+
+        let vessel = &output_structure.classes["Vessel"];
+        assert_eq!(vessel.getters_setters[0].signature, "Vessel_get_Parts(100:SpaceCenter.Vessel)->301(100:SpaceCenter.Part)");
+        assert_eq!(output_structure.getters_setters[0].signature, "get_UT()->1");
+        assert_eq!(output_structure.schema_hash, format!("0x{:016x}", schema_hash(vec![
+            "get_UT()->1".to_string(),
+            "Vessel_get_Parts(100:SpaceCenter.Vessel)->301(100:SpaceCenter.Part)".to_string(),
+        ])));
+        assert_eq!(schema_hash(vec![]), 0xcbf29ce484222325);
+    }
+
+    #[test]
+    fn test_core_service_types() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "AddStream": {
+                    "id": 1,
+                    "parameters": [{"name": "call", "type": {"code": "PROCEDURE_CALL"}}, {"name": "start", "type": {"code": "BOOL"}}],
+                    "return_type": {"code": "STREAM"}
+                },
+                "GetStatus": {"id": 2, "parameters": [], "return_type": {"code": "STATUS"}},
+                "RemoveStream": {"id": 3, "parameters": [{"name": "id", "type": {"code": "UINT64"}}]},
+                "get_Clients": {
+                    "id": 4,
+                    "parameters": [],
+                    "return_type": {"code": "LIST", "types": [{"code": "TUPLE", "types": [{"code": "BYTES"}, {"code": "STRING"}, {"code": "STRING"}]}]}
+                },
+                "get_CurrentGameScene": {"id": 5, "parameters": [], "return_type": {"code": "ENUMERATION", "service": "KRPC", "name": "GameScene"}}
+            },
+            "classes": {},
+            "enumerations": {"GameScene": {"values": [{"name": "SpaceCenter", "value": 0}, {"name": "Flight", "value": 1}]}}
+        }"#).unwrap();
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+
+        let methods = &output_structure.methods;
+        assert_eq!(methods[0].procedure, "AddStream");
+        assert_eq!(methods[0].arguments_signature, "&'a self, call: schema::ProcedureCall, start: bool");
+        assert_eq!(methods[0].arguments[0].encoder_function, "encode_procedure_call");
+        assert_eq!(methods[0].return_type_signature, "schema::Stream");
+        assert_eq!(methods[1].decoder_function, "decode_status");
+        assert_eq!(methods[2].arguments[0].encoder_function, "encode_uint64");
+
+        let getters = &output_structure.getters_setters;
+        assert_eq!(getters[0].return_type_signature, "Vec<(Vec<u8>, String, String)>");
+        assert_eq!(getters[0].decoder_function, "decode_list::<(Vec<u8>, String, String)>");
+        assert_eq!(getters[1].return_type_signature, "GameScene");
+        assert_eq!(getters[1].decoder_function, "decode_enumeration::<GameScene>");
+    }
+
+    #[test]
+    fn test_nullable_and_default_parameters() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "Vessel_Flight": {
+                    "id": 1,
+                    "parameters": [
+                        {"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                        {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}, "default_value": "AA==", "nullable": true}
+                    ],
+                    "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}
+                },
+                "set_TargetVessel": {
+                    "id": 2,
+                    "parameters": [{"name": "value", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}, "nullable": true}]
+                }
+            },
+            "classes": {"Vessel": {}, "ReferenceFrame": {}, "Flight": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+
+        let setter = &output_structure.getters_setters[0];
+        assert_eq!(setter.arguments_signature, "&'a self, value: Option<&Vessel<'_>>");
+        assert_eq!(setter.arguments[0].value, "value.map_or(0, |value| value.id)");
+        assert_eq!(setter.arguments[0].call_value, setter.arguments[0].value);
+
+        let flight = &output_structure.classes["Vessel"].methods[0];
+        assert_eq!(flight.arguments_signature, "&'a self, args: VesselFlightArgs<'_>");
+        assert!(flight.arguments[1].optional);
+        assert_eq!(flight.arguments[0].value, "self");
+        assert_eq!(flight.arguments[1].value, "reference_frame");
+        assert_eq!(flight.arguments[1].call_value, "args.reference_frame");
+        assert_eq!(output_structure.arguments_structs.len(), 1);
+        let arguments_struct = &output_structure.arguments_structs[0];
+        assert_eq!(arguments_struct.name, "VesselFlightArgs");
+        assert_eq!(arguments_struct.fields[0].name, "reference_frame");
+        assert_eq!(arguments_struct.fields[0].rust_type, "Option<&'b ReferenceFrame<'b>>");
+    }
+
+    #[test]
+    fn test_idiomatic_accessors() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "Vessel_get_Name": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Vessel_set_Name": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "value", "type": {"code": "STRING"}}]},
+                "Vessel_get_Type": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Vessel_get_Flight": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Vessel_Flight": {"id": 6, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+        assert_eq!(output_structure.getters_setters[0].name, "get_ut");
+        assert!(output_structure.warnings.is_empty());
+
+        let options = Options { idiomatic_accessors: true, ..Options::default() };
+        let output_structure = create_output_structure(&content, &options).unwrap();
+        assert_eq!(output_structure.getters_setters[0].name, "ut");
+        let names: Vec<&str> = output_structure.classes["Vessel"].getters_setters.iter()
+            .map(|method| method.name.as_str())
+            .collect();
+        assert_eq!(names, ["name", "set_name", "get_type", "get_flight"]);
+        assert_eq!(output_structure.warnings, [
+            "Vessel_get_Type: `type` is a keyword, keeping `get_type`",
+            "Vessel_get_Flight: `flight` is already used by another method, keeping `get_flight`",
+        ]);
+    }
+
+    #[test]
+    fn test_name_collisions() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "get_Ut": {"id": 2, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "New": {"id": 3, "parameters": []},
+                "Vessel_Recover": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}]},
+                "Vessel_static_Recover": {"id": 5, "parameters": []}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+        assert_eq!(output_structure.methods[0].name, "new_2");
+        let names: Vec<&str> = output_structure.getters_setters.iter().map(|method| method.name.as_str()).collect();
+        assert_eq!(names, ["get_ut", "get_ut_2"]);
+        let vessel = &output_structure.classes["Vessel"];
+        assert_eq!(vessel.methods[0].name, "recover");
+        assert_eq!(vessel.static_methods[0].name, "recover_2");
+        assert_eq!(output_structure.warnings, [
+            "New: `new` is already used by another method, renamed to `new_2`",
+            "get_Ut: `get_ut` is already used by another method, renamed to `get_ut_2`",
+            "Vessel_static_Recover: `recover` is already used by another method, renamed to `recover_2`",
+        ]);
+
+        let options = Options { collisions: Collisions::Skip, ..Options::default() };
+        let output_structure = create_output_structure(&content, &options).unwrap();
+        assert!(output_structure.methods.is_empty());
+        assert_eq!(output_structure.getters_setters.len(), 1);
+        assert_eq!(output_structure.getters_setters[0].procedure, "get_UT");
+        assert!(output_structure.classes["Vessel"].static_methods.is_empty());
+
+        let options = Options { collisions: Collisions::Error, ..Options::default() };
+        match create_output_structure(&content, &options) {
+            Err(Error::NameCollision { procedure, name }) => {
+                assert_eq!(procedure, "New");
+                assert_eq!(name, "new");
+            },
+            result => panic!("expected a name collision, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "Flight_get_GForce": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}], "return_type": {"code": "FLOAT"}},
+                "Flight_get_Type": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}], "return_type": {"code": "LIST", "types": [{"code": "ENUMERATION", "service": "SpaceCenter", "name": "VesselType"}]}},
+                "Flight_get_Frame": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}},
+                "Flight_get_Position": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}, {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}], "return_type": {"code": "DOUBLE"}},
+                "Flight_set_GForce": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}, {"name": "value", "type": {"code": "FLOAT"}}]},
+                "Flight_FetchSnapshot": {"id": 6, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}]},
+                "Vessel_get_Flight": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}
+            },
+            "classes": {"Flight": {}, "Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+        assert!(output_structure.classes["Flight"].snapshot.is_none());
+        assert_eq!(output_structure.classes["Flight"].methods[0].name, "fetch_snapshot");
+
+        let options = Options { snapshots: true, ..Options::default() };
+        let output_structure = create_output_structure(&content, &options).unwrap();
+        let flight = &output_structure.classes["Flight"];
+        assert_eq!(flight.methods[0].name, "fetch_snapshot_2");
+        let snapshot = flight.snapshot.as_ref().unwrap();
+        assert_eq!((snapshot.name.as_str(), snapshot.mask.as_str()), ("FlightSnapshot", "FlightFields"));
+        let fields: Vec<(&str, &str, u32)> = snapshot.fields.iter().map(|field| (field.name.as_str(), field.flag.as_str(), field.bit)).collect();
+        assert_eq!(fields, [("g_force", "G_FORCE", 0), ("r#type", "TYPE", 1)]);
+        assert!(output_structure.classes["Vessel"].snapshot.is_none());
+    }
+
+    #[test]
+    fn test_properties() {
+        let mut content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "Control_get_Throttle": {"id": 1, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}], "return_type": {"code": "FLOAT"}},
+                "Control_set_Throttle": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}, {"name": "value", "type": {"code": "FLOAT"}}]},
+                "Control_get_Speed": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}], "return_type": {"code": "DOUBLE"}},
+                "Control_get_Time": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}], "return_type": {"code": "DOUBLE"}},
+                "Control_set_Time": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}, {"name": "value", "type": {"code": "DOUBLE"}}]},
+                "Control_set_Target": {"id": 6, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}, {"name": "value", "type": {"code": "DOUBLE"}}]},
+                "Control_Speed": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Control"}}]},
+                "get_UT": {"id": 8, "parameters": [], "return_type": {"code": "DOUBLE"}}
+            },
+            "classes": {"Control": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        content.procedures.get_mut("Control_set_Time").unwrap().parameters[1].type_override = Some("crate::Time".to_string());
+
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+        let control = &output_structure.classes["Control"];
+        let properties: Vec<(&str, Option<&str>)> = control.properties.iter()
+            .map(|property| (property.name.as_str(), property.setter.as_ref().map(|setter| setter.procedure.as_str())))
+            .collect();
+        assert_eq!(properties, [("throttle", Some("Control_set_Throttle")), ("speed", None), ("time", Some("Control_set_Time"))]);
+        assert!(control.properties.iter().all(|property| !property.object));
+        assert_eq!(control.getters_setters.len(), 6);
+        assert_eq!(output_structure.properties[0].name, "ut");
+
+        let options = Options { property_objects: true, ..Options::default() };
+        let output_structure = create_output_structure(&content, &options).unwrap();
+        let control = &output_structure.classes["Control"];
+        let objects: Vec<&str> = control.properties.iter()
+            .filter(|property| property.object)
+            .map(|property| property.name.as_str())
+            .collect();
+        assert_eq!(objects, ["throttle", "speed_2"]);
+        // The setter takes a configured type, which `Property` can't convert
+        let getters_setters: Vec<&str> = control.getters_setters.iter().map(|method| method.name.as_str()).collect();
+        assert_eq!(getters_setters, ["get_time", "set_time", "set_target"]);
+        assert_eq!(output_structure.warnings, ["Control_get_Speed: `speed` is already used by another method, renamed to `speed_2`"]);
+        assert!(output_structure.properties[0].object);
+
+        let server_structure = create_server_structure(&output_structure);
+        assert_eq!(server_structure.procedures.len(), 8);
+    }
+
+    #[test]
+    fn test_server_structure() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 1,
+            "procedures": {
+                "Vessel_get_Name": {
+                    "id": 2,
+                    "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}],
+                    "return_type": {"code": "STRING"}
+                },
+                "Vessel_SetPosition": {
+                    "id": 3,
+                    "parameters": [
+                        {"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                        {"name": "position", "type": {"code": "TUPLE", "types": [{"code": "DOUBLE"}, {"code": "DOUBLE"}]}}
+                    ]
+                }
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let output_structure = create_output_structure(&content, &Options::default()).unwrap();
+        let result = create_server_structure(&output_structure);
+
+        assert_eq!(result.classes, vec!["Vessel".to_string()]);
+        assert_eq!(result.procedures.len(), 2);
+        let set_position = &result.procedures[0];
+        assert_eq!(set_position.name, "vessel_set_position");
+        assert_eq!(set_position.arguments[1].name, "position");
+        assert_eq!(set_position.arguments[1].rust_type, "(f64, f64)");
+        assert_eq!(set_position.return_type, "()");
+        let get_name = &result.procedures[1];
+        assert_eq!(get_name.name, "vessel_get_name");
+        assert_eq!(get_name.arguments[0].rust_type, "Vessel");
+        assert_eq!(get_name.return_type, "String");
+    }
 }
\ No newline at end of file
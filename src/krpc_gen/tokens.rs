@@ -0,0 +1,1164 @@
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+use crate::error::{Error, InvalidCode};
+use crate::format::{self, FormatError};
+use crate::original;
+use crate::output;
+use crate::options::{Layout, Options};
+use crate::parser::property_object_methods;
+use crate::writer;
+
+/// Client bindings of a service, built as tokens instead of from the
+/// handlebars templates. Generates the same code as `service.rs.hbs`.
+pub fn service_tokens(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<TokenStream, Error> {
+    let service = ident(service_name)?;
+    let service_doc = doc(&output_structure.documentation);
+    let krpc_version = match &options.krpc_version {
+        Some(version) => quote! { Some(#version) },
+        None => quote! { None },
+    };
+    let schema_hash = syn::LitInt::new(&output_structure.schema_hash, Span::call_site());
+
+    let all_methods: Vec<&output::Method> = output_structure.methods.iter()
+        .chain(&output_structure.getters_setters)
+        .chain(property_object_methods(&output_structure.properties))
+        .chain(output_structure.classes.values().flat_map(|class| {
+            class.methods.iter()
+                .chain(&class.getters_setters)
+                .chain(property_object_methods(&class.properties))
+                .chain(&class.static_methods)
+        }))
+        .collect();
+    let signatures = all_methods.iter().map(|method| {
+        let procedure = &method.procedure;
+        let signature = &method.signature;
+        quote! { (#procedure, #signature), }
+    });
+
+    let arguments_structs = output_structure.arguments_structs.iter()
+        .map(arguments_struct)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let service_constant = if options.call_helper {
+        quote! {
+            /// Name of the service passed to `Connection::call`
+            pub const SERVICE: &str = #service_name;
+        }
+    } else {
+        quote! {}
+    };
+    let procedure_ids = if options.procedure_ids {
+        let service_id = Literal::u64_unsuffixed(output_structure.service_id);
+        let ids = all_methods.iter().map(|method| {
+            let procedure = &method.procedure;
+            let id = Literal::u64_unsuffixed(method.id);
+            quote! { (#procedure, #id), }
+        });
+        let procedure_names = &output_structure.procedure_names;
+        quote! {
+            pub const SERVICE_ID: u32 = #service_id;
+
+            /// Every procedure of the schema the ids were taken from, including ones
+            /// left out of this module
+            pub const SCHEMA_PROCEDURES: &[&str] = &[#(#procedure_names,)*];
+
+            /// Procedure ids this module was generated from
+            pub const PROCEDURE_IDS: &[(&str, u32)] = &[#(#ids)*];
+        }
+    } else {
+        quote! {}
+    };
+    let check_procedure_ids = if options.procedure_ids {
+        quote! {
+            /// Check that the server still runs the schema the procedure ids were
+            /// taken from. `KRPC.GetServices` does not report ids, so besides the
+            /// checks of `check_schema` any procedure added to or removed from the
+            /// service, including ones left out of this module, is reported as a
+            /// possible renumbering.
+            pub async fn check_procedure_ids(&self) -> Result<(), error::Error> {
+                let (version, service) = self.server_schema().await?;
+                let procedures: Vec<&str> = service.iter()
+                    .flat_map(|service| service.procedures.iter())
+                    .map(|procedure| procedure.name.as_str())
+                    .collect();
+                let added: Vec<String> = procedures.iter()
+                    .filter(|procedure| !SCHEMA_PROCEDURES.contains(procedure))
+                    .map(|procedure| procedure.to_string())
+                    .collect();
+                let removed: Vec<String> = SCHEMA_PROCEDURES.iter()
+                    .filter(|procedure| !procedures.contains(procedure))
+                    .map(|procedure| procedure.to_string())
+                    .collect();
+                let mismatch = schema_mismatch(version.clone(), service.as_ref());
+                if mismatch.is_none() && added.is_empty() && removed.is_empty() {
+                    return Ok(());
+                }
+                let mut mismatch = mismatch.unwrap_or_else(|| error::SchemaMismatch {
+                    service: #service_name.to_string(),
+                    expected_version: KRPC_VERSION.map(str::to_string),
+                    found_version: version,
+                    missing: Vec::new(),
+                    changed: Vec::new(),
+                    added: Vec::new(),
+                });
+                for procedure in removed {
+                    if !mismatch.missing.contains(&procedure) {
+                        mismatch.missing.push(procedure);
+                    }
+                }
+                mismatch.added = added;
+                Err(error::Error::SchemaMismatch(mismatch))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let service_methods = output_structure.methods.iter()
+        .chain(&output_structure.getters_setters)
+        .map(|method| method_tokens(service_name, method, false, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let service_properties = output_structure.properties.iter()
+        .filter(|property| property.object)
+        .map(|property| property_tokens(service_name, property, quote! { None }, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let property_import = if options.property_objects {
+        quote! { use crate::property; }
+    } else {
+        quote! {}
+    };
+    let foreign_types = output_structure.foreign_types.iter()
+        .map(|foreign_type| {
+            let module = ident(&foreign_type.module)?;
+            let name = ident(&foreign_type.name)?;
+            Ok(quote! { use super::#module::#name; })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let classes_and_enumerations = if options.layout == Layout::Modules {
+        module_declarations(output_structure)?
+    } else {
+        let classes = output_structure.classes.values()
+            .map(|class| class_tokens(service_name, class, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let enumerations = output_structure.enumerations.iter()
+            .map(|enumeration| enumeration_tokens(enumeration, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        quote! {
+            #(#classes)*
+
+            #(#enumerations)*
+        }
+    };
+
+    Ok(quote! {
+        #[allow(unused_imports)]
+        use std::collections::{HashMap, HashSet};
+
+        use crate::connection;
+        use crate::connection::Connection;
+        use crate::decoder;
+        use crate::encoder;
+        #[allow(unused_imports)]
+        use crate::encoder::KRPCEncode;
+        use crate::error;
+        #property_import
+
+        use super::schema;
+        #(#foreign_types)*
+
+        /// kRPC version this module was generated from, if known
+        pub const KRPC_VERSION: Option<&str> = #krpc_version;
+
+        /// Hash of the procedure signatures this module was generated from
+        pub const SCHEMA_HASH: u64 = #schema_hash;
+
+        /// Signature of every procedure this module was generated from
+        pub const PROCEDURE_SIGNATURES: &[(&str, &str)] = &[#(#signatures)*];
+
+        #(#arguments_structs)*
+
+        #service_constant
+
+        #procedure_ids
+
+        #service_doc
+        pub struct #service<'a> {
+            conn: &'a Connection,
+        }
+        impl<'a> #service<'a> {
+            pub fn new(conn: &'a Connection) -> #service<'a> {
+                #service {
+                    conn,
+                }
+            }
+
+            /// Version of the server and its procedures of this service
+            async fn server_schema(&self) -> Result<(String, Option<schema::Service>), error::Error> {
+                let result = self.conn.execute_procedure("KRPC", "GetStatus", Vec::new()).await?;
+                let status = decoder::decode_status(result, self.conn)?;
+                let result = self.conn.execute_procedure("KRPC", "GetServices", Vec::new()).await?;
+                let services = decoder::decode_services(result, self.conn)?;
+                let service = services.services.into_iter().find(|service| service.name == #service_name);
+                Ok((status.version, service))
+            }
+
+            /// Compare the schema this module was generated from with the one the
+            /// server reports. Fails with `error::Error::SchemaMismatch` listing the
+            /// procedures that are missing or have a different signature, or when
+            /// `KRPC_VERSION` is set and the server runs another version. Procedures
+            /// the server added are accepted.
+            pub async fn check_schema(&self) -> Result<(), error::Error> {
+                let (version, service) = self.server_schema().await?;
+                match schema_mismatch(version, service.as_ref()) {
+                    Some(mismatch) => Err(error::Error::SchemaMismatch(mismatch)),
+                    None => Ok(()),
+                }
+            }
+
+            #check_procedure_ids
+
+            #(#service_methods)*
+            #(#service_properties)*
+        }
+
+        /// Differences between the schema this module was generated from and the
+        /// service the server reports, if any
+        fn schema_mismatch(found_version: String, service: Option<&schema::Service>) -> Option<error::SchemaMismatch> {
+            let signatures: Vec<String> = service.iter()
+                .flat_map(|service| service.procedures.iter())
+                .map(procedure_signature)
+                .collect();
+            let version_matches = KRPC_VERSION.map_or(true, |version| version == found_version);
+            if version_matches && schema_hash(signatures) == SCHEMA_HASH {
+                return None;
+            }
+
+            let mut missing = Vec::new();
+            let mut changed = Vec::new();
+            for (procedure, signature) in PROCEDURE_SIGNATURES {
+                match service.and_then(|service| service.procedures.iter().find(|p| p.name == *procedure)) {
+                    None => missing.push(procedure.to_string()),
+                    Some(p) if procedure_signature(p) != *signature => changed.push(procedure.to_string()),
+                    Some(_) => {},
+                }
+            }
+            if version_matches && missing.is_empty() && changed.is_empty() {
+                return None;
+            }
+            Some(error::SchemaMismatch {
+                service: #service_name.to_string(),
+                expected_version: KRPC_VERSION.map(str::to_string),
+                found_version,
+                missing,
+                changed,
+                added: Vec::new(),
+            })
+        }
+
+        fn procedure_signature(procedure: &schema::Procedure) -> String {
+            let parameters: Vec<String> = procedure.parameters.iter()
+                .map(|parameter| type_signature(parameter.r#type.as_ref()))
+                .collect();
+            format!("{}({})->{}", procedure.name, parameters.join(","), type_signature(procedure.return_type.as_ref()))
+        }
+
+        fn type_signature(r#type: Option<&schema::Type>) -> String {
+            let r#type = match r#type {
+                Some(r#type) => r#type,
+                None => return "0".to_string(),
+            };
+            let mut result = r#type.code.to_string();
+            if !r#type.name.is_empty() {
+                result += &format!(":{}.{}", r#type.service, r#type.name);
+            }
+            if !r#type.types.is_empty() {
+                let inner: Vec<String> = r#type.types.iter().map(|t| type_signature(Some(t))).collect();
+                result += &format!("({})", inner.join(","));
+            }
+            result
+        }
+
+        fn schema_hash(mut signatures: Vec<String>) -> u64 {
+            signatures.sort();
+            let mut hash: u64 = 0xcbf29ce484222325;
+            for byte in signatures.join("\n").bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+            hash
+        }
+
+        #classes_and_enumerations
+    })
+}
+
+/// The `mod` declarations of `mod.rs` with `Layout::Modules`, declaring the
+/// file of each class and `enums.rs` and re-exporting their items
+fn module_declarations(output_structure: &output::OutputStructure) -> Result<TokenStream, Error> {
+    let classes = output_structure.classes.values().map(|class| {
+        let module = ident(&writer::class_module(&class.name).0)?;
+        let name = ident(&class.name)?;
+        let snapshot = match &class.snapshot {
+            Some(snapshot) => {
+                let snapshot_name = ident(&snapshot.name)?;
+                let mask = ident(&snapshot.mask)?;
+                quote! {
+                    pub use #module::#snapshot_name;
+                    pub use #module::#mask;
+                }
+            },
+            None => quote! {},
+        };
+        Ok(quote! {
+            mod #module;
+            pub use #module::#name;
+            #snapshot
+        })
+    }).collect::<Result<Vec<_>, Error>>()?;
+    let enumerations = if output_structure.enumerations.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            mod enums;
+            pub use enums::*;
+        }
+    };
+    Ok(quote! {
+        #(#classes)*
+        #enumerations
+    })
+}
+
+/// Render the client bindings of a service through the tokens backend
+pub fn render(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<String, Error> {
+    format_tokens(service_name, service_tokens(service_name, output_structure, options)?, options)
+}
+
+/// Render the files of the service directory for `Layout::Modules` through
+/// the tokens backend, like `writer::render_modules`
+pub fn render_modules(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<Vec<(String, String)>, Error> {
+    let options = &Options { layout: Layout::Modules, ..options.clone() };
+    let mut files = vec![("mod.rs".to_string(), render(service_name, output_structure, options)?)];
+    for class in output_structure.classes.values() {
+        let class_tokens = class_tokens(service_name, class, options)?;
+        let tokens = quote! {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #class_tokens
+        };
+        files.push((writer::class_module(&class.name).1, format_tokens(service_name, tokens, options)?));
+    }
+    if !output_structure.enumerations.is_empty() {
+        let enumerations = output_structure.enumerations.iter()
+            .map(|enumeration| enumeration_tokens(enumeration, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tokens = quote! {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#enumerations)*
+        };
+        files.push(("enums.rs".to_string(), format_tokens(service_name, tokens, options)?));
+    }
+    Ok(files)
+}
+
+fn format_tokens(service_name: &str, tokens: TokenStream, options: &Options) -> Result<String, Error> {
+    let source = format!("{}\n{}", writer::GENERATED_HEADER, tokens);
+    format::format(&source, options.format).map_err(|e| match e {
+        FormatError::Parse { line, column, message } => Error::InvalidCode(Box::new(InvalidCode {
+            service: service_name.to_string(),
+            line,
+            column,
+            message,
+            procedure: None,
+            template: "tokens backend".to_string(),
+            template_line: None,
+        })),
+        FormatError::Rustfmt(message) => Error::Rustfmt(message),
+    })
+}
+
+fn class_tokens(service_name: &str, class: &output::Class, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&class.name)?;
+    let class_doc = doc(&class.documentation);
+    let methods = class.methods.iter()
+        .chain(&class.getters_setters)
+        .map(|method| method_tokens(service_name, method, false, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let properties = class.properties.iter()
+        .filter(|property| property.object)
+        .map(|property| property_tokens(service_name, property, quote! { Some(self) }, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let static_methods = class.static_methods.iter()
+        .map(|method| method_tokens(service_name, method, true, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let fetch_snapshot = class.snapshot.as_ref().map(|snapshot| fetch_snapshot(service_name, snapshot, options)).transpose()?;
+    let snapshot = class.snapshot.as_ref().map(|snapshot| snapshot_tokens(&class.name, snapshot, options)).transpose()?;
+    let attributes = attributes(&class.attributes)?;
+    let derives = derives(&class.derives)?;
+    Ok(quote! {
+        #class_doc
+        #attributes
+        #[derive(Debug #derives)]
+        pub struct #name<'a> {
+            pub(crate) id: u64,
+            pub(crate) conn: &'a Connection,
+        }
+        impl<'a> #name<'a> {
+            #(#methods)*
+            #(#properties)*
+            #(#static_methods)*
+            #fetch_snapshot
+        }
+        impl<'a> decoder::KRPCDecode<'a> for #name<'a> {
+            fn krpc_decode(input: Vec<u8>, conn: &'a Connection) -> Result<Self, decoder::Error> {
+                Ok(Self {
+                    id: decoder::decode_class(input, conn)?,
+                    conn
+                })
+            }
+        }
+        impl<'a> encoder::KRPCEncode for #name<'a> {
+            fn krpc_encode(&self) -> Result<Vec<u8>, encoder::Error> {
+                encoder::encode_u64(self.id)
+            }
+        }
+        #snapshot
+    })
+}
+
+/// Method returning a `property::Property` for a property, read-only if it
+/// has no setter
+fn property_tokens(service_name: &str, property: &output::Property, instance: TokenStream, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&property.name)?;
+    let property_doc = doc(&property.getter.documentation);
+    let value_type = return_type(&property.getter)?;
+    let procedure_call = |method: &output::Method| {
+        let procedure = if options.procedure_ids {
+            let id = Literal::u64_unsuffixed(method.id);
+            quote! {
+                service_id: SERVICE_ID,
+                procedure_id: #id,
+            }
+        } else {
+            let procedure = &method.procedure;
+            quote! {
+                service: #service_name.to_string(),
+                procedure: #procedure.to_string(),
+            }
+        };
+        quote! {
+            schema::ProcedureCall {
+                #procedure
+                ..Default::default()
+            },
+        }
+    };
+    let getter = procedure_call(&property.getter);
+    let (property_type, constructor, setter) = match &property.setter {
+        Some(setter) => (quote! { property::Property<'a, #value_type> }, quote! { new }, procedure_call(setter)),
+        None => (quote! { property::Property<'a, #value_type, property::ReadOnly> }, quote! { read_only }, quote! {}),
+    };
+    Ok(quote! {
+        #property_doc
+        pub fn #name(&'a self) -> #property_type {
+            property::Property::#constructor(
+                self.conn,
+                #instance,
+                #getter
+                #setter
+            )
+        }
+    })
+}
+
+/// `fetch_snapshot` method of a class, reading the selected properties with
+/// one `Connection::execute_batch`
+fn fetch_snapshot(service_name: &str, snapshot: &output::Snapshot, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&snapshot.name)?;
+    let mask = ident(&snapshot.mask)?;
+    let calls = snapshot.fields.iter().map(|field| {
+        let flag = ident(&field.flag)?;
+        let procedure = if options.procedure_ids {
+            let id = Literal::u64_unsuffixed(field.getter.id);
+            quote! {
+                service_id: SERVICE_ID,
+                procedure_id: #id,
+            }
+        } else {
+            let procedure = &field.getter.procedure;
+            quote! {
+                service: #service_name.to_string(),
+                procedure: #procedure.to_string(),
+            }
+        };
+        Ok(quote! {
+            if fields.contains(#mask::#flag) {
+                calls.push(schema::ProcedureCall {
+                    #procedure
+                    arguments: Vec::from([schema::Argument {
+                        position: 0,
+                        value: this.clone(),
+                    }]),
+                    ..Default::default()
+                });
+            }
+        })
+    }).collect::<Result<Vec<_>, Error>>()?;
+    let decoded = snapshot.fields.iter().map(|field| {
+        let flag = ident(&field.flag)?;
+        let field_name = ident(&field.name)?;
+        let decoder = decoder(&field.getter)?;
+        let value = match &field.getter.return_type_override {
+            Some(_) => quote! { return_value.into() },
+            None => quote! { return_value },
+        };
+        Ok(quote! {
+            if fields.contains(#mask::#flag) {
+                if let Some(result) = results.next() {
+                    let return_value = decoder::#decoder(result, self.conn)?;
+                    snapshot.#field_name = Some(#value);
+                }
+            }
+        })
+    }).collect::<Result<Vec<_>, Error>>()?;
+    Ok(quote! {
+        /// Read the properties selected in `fields` in one batch of calls.
+        /// Properties that are not selected are left as `None`. Fails with
+        /// `error::Error::BatchResults` if the server does not return a result
+        /// per call.
+        pub async fn fetch_snapshot(&'a self, fields: #mask) -> Result<#name, error::Error> {
+            let this = self.krpc_encode()?;
+            let mut calls = Vec::new();
+            #(#calls)*
+            let expected = calls.len();
+            let results = self.conn.execute_batch(calls).await?;
+            if results.len() != expected {
+                return Err(error::Error::BatchResults {
+                    expected,
+                    found: results.len(),
+                });
+            }
+            let mut results = results.into_iter();
+            let mut snapshot = #name::default();
+            #(#decoded)*
+            Ok(snapshot)
+        }
+    })
+}
+
+/// The `{Class}Snapshot` struct of a class and its `{Class}Fields` mask
+fn snapshot_tokens(class_name: &str, snapshot: &output::Snapshot, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&snapshot.name)?;
+    let mask = ident(&snapshot.mask)?;
+    let snapshot_doc = format!(" Properties of `{}` read by `{}::fetch_snapshot`", class_name, class_name);
+    let mask_doc = format!(" Properties of `{}` to read with `fetch_snapshot`, combined\n with `|`", class_name);
+    let mask_doc = mask_doc.lines().map(|line| quote! { #[doc = #line] });
+    let serde = if options.snapshot_serde {
+        quote! { , serde::Serialize, serde::Deserialize }
+    } else {
+        quote! {}
+    };
+    let fields = snapshot.fields.iter().map(|field| {
+        let field_name = ident(&field.name)?;
+        let field_type = return_type(&field.getter)?;
+        Ok(quote! { pub #field_name: Option<#field_type>, })
+    }).collect::<Result<Vec<_>, Error>>()?;
+    let flags = snapshot.fields.iter().map(|field| ident(&field.flag)).collect::<Result<Vec<_>, _>>()?;
+    let bits = snapshot.fields.iter().map(|field| Literal::u32_unsuffixed(field.bit));
+    Ok(quote! {
+        #[doc = #snapshot_doc]
+        #[derive(Debug, Clone, Default #serde)]
+        pub struct #name {
+            #(#fields)*
+        }
+        #(#mask_doc)*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct #mask(u64);
+        impl #mask {
+            #(pub const #flags: Self = Self(1 << #bits);)*
+
+            /// No properties
+            pub const fn empty() -> Self {
+                Self(0)
+            }
+            /// Every property of the snapshot
+            pub const fn all() -> Self {
+                Self(#(Self::#flags.0)|*)
+            }
+            pub const fn contains(self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+            pub const fn bits(self) -> u64 {
+                self.0
+            }
+        }
+        impl std::ops::BitOr for #mask {
+            type Output = Self;
+
+            fn bitor(self, other: Self) -> Self {
+                Self(self.0 | other.0)
+            }
+        }
+        impl std::ops::BitOrAssign for #mask {
+            fn bitor_assign(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+        }
+    })
+}
+
+fn enumeration_tokens(enumeration: &output::Enumeration, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&enumeration.name)?;
+    let enumeration_doc = doc(&enumeration.documentation);
+    let variant_names = enumeration.values.iter().map(|value| ident(&value.name)).collect::<Result<Vec<_>, _>>()?;
+    let variants = enumeration.values.iter().zip(&variant_names).map(|(value, variant)| {
+        let value_doc = doc(&value.documentation);
+        let id = Literal::u64_unsuffixed(value.id);
+        quote! {
+            #value_doc
+            #variant = #id,
+        }
+    });
+    let arms = enumeration.values.iter().zip(&variant_names).map(|(value, variant)| {
+        let id = Literal::u64_unsuffixed(value.id);
+        quote! { #id => Ok(#name::#variant), }
+    });
+    let attributes = attributes(&enumeration.attributes)?;
+    let serde = if options.snapshot_serde {
+        quote! { , serde::Serialize, serde::Deserialize }
+    } else {
+        quote! {}
+    };
+    let derives = derives(&enumeration.derives)?;
+    Ok(quote! {
+        #enumeration_doc
+        #attributes
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash #serde #derives)]
+        pub enum #name {
+            #(#variants)*
+        }
+        impl<'a> decoder::KRPCDecode<'a> for #name {
+            fn krpc_decode(input: Vec<u8>, conn: &'a Connection) -> Result<Self, decoder::Error> {
+                match decoder::decode_sint32(input, conn)? {
+                    #(#arms)*
+                    value => Err(decoder::Error::UnknownEnumerationValue(value)),
+                }
+            }
+        }
+        impl encoder::KRPCEncode for #name {
+            fn krpc_encode(&self) -> Result<Vec<u8>, encoder::Error> {
+                encoder::encode_sint32(*self as i32)
+            }
+        }
+    })
+}
+
+fn arguments_struct(arguments_struct: &output::ArgumentsStruct) -> Result<TokenStream, Error> {
+    let name = ident(&arguments_struct.name)?;
+    let procedure = &arguments_struct.procedure;
+    let struct_doc = format!(" Optional arguments of `{}`. Arguments left\n as `None` are not sent, so the server applies its default.", procedure);
+    let lifetime = if arguments_struct.has_lifetime { quote! { <'b> } } else { quote! {} };
+    let fields = arguments_struct.fields.iter().map(|field| {
+        let field_name = ident(&field.name)?;
+        let field_type = match (&field.type_override, &field.r#type.code) {
+            (Some(type_override), _) => parse_type(type_override)?,
+            (None, original::Code::Class) => {
+                let class_type = rust_type(&field.r#type, &quote! { 'b })?;
+                quote! { &'b #class_type }
+            },
+            (None, _) => rust_type(&field.r#type, &quote! { 'b })?,
+        };
+        Ok(quote! { pub #field_name: Option<#field_type>, })
+    }).collect::<Result<Vec<_>, Error>>()?;
+    let doc_lines = struct_doc.lines().map(|line| quote! { #[doc = #line] });
+    Ok(quote! {
+        #(#doc_lines)*
+        #[derive(Debug, Default)]
+        pub struct #name #lifetime {
+            #(#fields)*
+        }
+    })
+}
+
+fn method_tokens(service_name: &str, method: &output::Method, is_static: bool, options: &Options) -> Result<TokenStream, Error> {
+    let name = ident(&method.name)?;
+    let method_doc = doc(&method.documentation);
+    let conn = if is_static { quote! { conn } } else { quote! { self.conn } };
+
+    let receiver = if is_static { quote! { conn: &'a Connection } } else { quote! { &'a self } };
+    let parameters = method.arguments.iter()
+        .filter(|argument| argument.name != "this" && !argument.optional)
+        .map(|argument| {
+            let argument_name = ident(&argument.name)?;
+            let argument_type = argument_type(argument)?;
+            Ok(quote! { , #argument_name: #argument_type })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let args = match &method.arguments_struct {
+        Some(arguments_struct) => {
+            let struct_name = ident(&arguments_struct.name)?;
+            if arguments_struct.has_lifetime {
+                quote! { , args: #struct_name<'_> }
+            } else {
+                quote! { , args: #struct_name }
+            }
+        },
+        None => quote! {},
+    };
+
+    let return_type = return_type(method)?;
+    let attributes = attributes(&method.attributes)?;
+
+    if options.call_helper {
+        let call_type = match &method.return_type {
+            Some(return_type) => rust_type(return_type, &quote! { 'a })?,
+            None => quote! { () },
+        };
+        let call_values = method.arguments.iter().map(call_value).collect::<Result<Vec<_>, _>>()?;
+        let call = if options.procedure_ids {
+            let id = Literal::u64_unsuffixed(method.id);
+            quote! { #conn.call_by_id::<#call_type>(SERVICE_ID, #id, (#(#call_values,)*)).await }
+        } else {
+            let procedure = &method.procedure;
+            quote! { #conn.call::<#call_type>(SERVICE, #procedure, (#(#call_values,)*)).await }
+        };
+        let convert = method.return_type_override.as_ref().map(|_| quote! { .map(Into::into) });
+        return Ok(quote! {
+            #method_doc
+            #attributes
+            pub async fn #name(#receiver #(#parameters)* #args) -> Result<#return_type, error::Error> {
+                #call #convert
+            }
+        });
+    }
+
+    let declare_arguments = if method.arguments.is_empty() {
+        quote! { let arguments = Vec::new(); }
+    } else {
+        quote! { let mut arguments = Vec::new(); }
+    };
+    let push_arguments = method.arguments.iter().map(|argument| {
+        let position = Literal::u64_unsuffixed(argument.position);
+        let value = argument_value(argument)?;
+        let push = quote! {
+            arguments.push(schema::Argument {
+                position: #position,
+                value: #value.krpc_encode()?,
+            });
+        };
+        if argument.optional {
+            let argument_name = ident(&argument.name)?;
+            Ok(quote! {
+                if let Some(#argument_name) = args.#argument_name {
+                    #push
+                }
+            })
+        } else {
+            Ok(push)
+        }
+    }).collect::<Result<Vec<_>, Error>>()?;
+
+    let execute = if options.procedure_ids {
+        let id = Literal::u64_unsuffixed(method.id);
+        quote! { #conn.execute_procedure_by_id(SERVICE_ID, #id, arguments).await? }
+    } else {
+        let procedure = &method.procedure;
+        quote! { #conn.execute_procedure(#service_name, #procedure, arguments).await? }
+    };
+    let decoder = decoder(method)?;
+    let return_value = match &method.return_type {
+        Some(return_type) if return_type.code == original::Code::Class => {
+            let class = ident(type_name(return_type)?)?;
+            let class_conn = if is_static { quote! { &conn } } else { quote! { &self.conn } };
+            quote! { #class { id: return_value, conn: #class_conn } }
+        },
+        Some(_) => quote! { return_value },
+        None => quote! { () },
+    };
+    let return_value = match &method.return_type_override {
+        Some(_) => quote! { #return_value.into() },
+        None => return_value,
+    };
+
+    Ok(quote! {
+        #method_doc
+        #attributes
+        pub async fn #name(#receiver #(#parameters)* #args) -> Result<#return_type, error::Error> {
+            #declare_arguments
+            #(#push_arguments)*
+            let result = #execute;
+            let return_value = decoder::#decoder(result, #conn)?;
+            Ok(#return_value)
+        }
+    })
+}
+
+/// Type returned by the method, like `output::Method::return_type_signature`
+fn return_type(method: &output::Method) -> Result<TokenStream, Error> {
+    match (&method.return_type_override, &method.return_type) {
+        (Some(return_type_override), _) => parse_type(return_type_override),
+        (None, Some(return_type)) => rust_type(return_type, &quote! { 'a }),
+        (None, None) => Ok(quote! { () }),
+    }
+}
+
+fn argument_type(argument: &output::Argument) -> Result<TokenStream, Error> {
+    if let Some(type_override) = &argument.type_override {
+        return parse_type(type_override);
+    }
+    match argument.r#type.code {
+        original::Code::Class => {
+            let class = rust_type(&argument.r#type, &quote! { '_ })?;
+            if argument.nullable {
+                Ok(quote! { Option<&#class> })
+            } else {
+                Ok(quote! { &#class })
+            }
+        },
+        _ => rust_type(&argument.r#type, &quote! { '_ }),
+    }
+}
+
+fn argument_value(argument: &output::Argument) -> Result<TokenStream, Error> {
+    if argument.name == "this" {
+        return Ok(quote! { self });
+    }
+    let name = ident(&argument.name)?;
+    // A configured type is converted into the schema's type before encoding
+    let source = match &argument.type_override {
+        Some(_) => {
+            let schema_type = rust_type(&argument.r#type, &quote! { '_ })?;
+            quote! { Into::<#schema_type>::into(#name) }
+        },
+        None => quote! { #name },
+    };
+    match argument.r#type.code {
+        // A null object is sent as id 0
+        original::Code::Class if argument.nullable && !argument.optional => Ok(quote! { #name.map_or(0, |#name| #name.id) }),
+        _ => Ok(source),
+    }
+}
+
+/// Element of the arguments tuple passed to `Connection::call`, like
+/// `output::Argument::call_value`
+fn call_value(argument: &output::Argument) -> Result<TokenStream, Error> {
+    let value = argument_value(argument)?;
+    if !argument.optional {
+        return Ok(value);
+    }
+    let name = ident(&argument.name)?;
+    if name == value.to_string() {
+        Ok(quote! { args.#name })
+    } else {
+        Ok(quote! { args.#name.map(|#name| #value) })
+    }
+}
+
+/// Decoder function of the method's return type, with the generic arguments
+/// of collections, tuples and enumerations
+fn decoder(method: &output::Method) -> Result<TokenStream, Error> {
+    // `decode_list::<...>` without its generic arguments
+    let function = match method.decoder_function.split_once("::") {
+        Some((function, _)) => ident(function)?,
+        None => ident(&method.decoder_function)?,
+    };
+    let return_type = match &method.return_type {
+        Some(return_type) => return_type,
+        None => return Ok(quote! { #function }),
+    };
+    let lifetime = quote! { '_ };
+    match return_type.code {
+        original::Code::Enumeration | original::Code::Tuple => {
+            let generic = rust_type(return_type, &lifetime)?;
+            Ok(quote! { #function::<#generic> })
+        },
+        original::Code::List | original::Code::Set | original::Code::Dictionary => {
+            let generics = return_type.types.iter().flatten()
+                .map(|t| rust_type(t, &lifetime))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(quote! { #function::<#(#generics),*> })
+        },
+        _ => Ok(quote! { #function }),
+    }
+}
+
+/// Rust type of a value of the given kRPC type, mirroring `parser::rust_type`
+fn rust_type(r#type: &original::Type, lifetime: &TokenStream) -> Result<TokenStream, Error> {
+    let inner = r#type.types.iter().flatten()
+        .map(|t| rust_type(t, lifetime))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(match r#type.code {
+        original::Code::None => quote! { () },
+        original::Code::Double => quote! { f64 },
+        original::Code::Float => quote! { f32 },
+        original::Code::Sint32 => quote! { i32 },
+        original::Code::Sint64 => quote! { i64 },
+        original::Code::Uint32 => quote! { u32 },
+        original::Code::Uint64 => quote! { u64 },
+        original::Code::Bool => quote! { bool },
+        original::Code::String => quote! { String },
+        original::Code::Bytes => quote! { Vec<u8> },
+        original::Code::Class => {
+            let name = ident(type_name(r#type)?)?;
+            quote! { #name<#lifetime> }
+        },
+        original::Code::Enumeration => {
+            let name = ident(type_name(r#type)?)?;
+            quote! { #name }
+        },
+        original::Code::Event => quote! { schema::Event },
+        original::Code::ProcedureCall => quote! { schema::ProcedureCall },
+        original::Code::Stream => quote! { schema::Stream },
+        original::Code::Status => quote! { schema::Status },
+        original::Code::Services => quote! { schema::Services },
+        original::Code::Tuple => quote! { (#(#inner,)*) },
+        original::Code::List => quote! { Vec<#(#inner),*> },
+        original::Code::Set => quote! { HashSet<#(#inner),*> },
+        original::Code::Dictionary => quote! { HashMap<#(#inner),*> },
+    })
+}
+
+/// Name of a class or enumeration type
+fn type_name(r#type: &original::Type) -> Result<&str, Error> {
+    r#type.name.as_deref().ok_or_else(|| Error::Tokens(format!("{} type without a name", r#type.code.name())))
+}
+
+/// Identifier for a name of the output structure, which
+/// `parser::raw_identifier` already made a raw identifier if it is a
+/// keyword, as the templates print it
+fn ident(name: &str) -> Result<Ident, Error> {
+    syn::parse_str::<Ident>(name)
+        .map_err(|_| Error::Tokens(format!("`{}` is not a valid identifier", name)))
+}
+
+/// Attributes from the configuration file
+fn attributes(attributes: &[String]) -> Result<TokenStream, Error> {
+    let attributes = attributes.iter()
+        .map(|attribute| parse(attribute, "attribute"))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(quote! { #(#[#attributes])* })
+}
+
+/// Derives from the configuration file, each preceded by a comma
+fn derives(derives: &[String]) -> Result<TokenStream, Error> {
+    let derives = derives.iter()
+        .map(|derive| parse(derive, "derive"))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(quote! { #(, #derives)* })
+}
+
+/// Type from the configuration file
+fn parse_type(r#type: &str) -> Result<TokenStream, Error> {
+    parse(r#type, "type")
+}
+
+fn parse(source: &str, what: &str) -> Result<TokenStream, Error> {
+    source.parse()
+        .map_err(|e| Error::Tokens(format!("invalid {} `{}`: {}", what, source, e)))
+}
+
+fn doc(documentation: &str) -> TokenStream {
+    let lines = writer::doc_lines(documentation).into_iter()
+        .map(|line| line.trim_start_matches("///").to_string());
+    quote! { #(#[doc = #lines])* }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parser;
+
+    const SCHEMA: &str = r#"{
+        "id": 2,
+        "documentation": "<doc><summary>Space center.</summary></doc>",
+        "procedures": {
+            "WarpTo": {"id": 1, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}, {"name": "maxRailsRate", "type": {"code": "FLOAT"}, "default_value": "AACAPw=="}]},
+            "get_Vessels": {"id": 2, "parameters": [], "return_type": {"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}]}},
+            "Vessel_Flight": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}, "default_value": "AA==", "nullable": true}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}},
+            "Vessel_get_Type": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "VesselType"}},
+            "Vessel_get_Position": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "referenceFrame", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}, "nullable": true}], "return_type": {"code": "TUPLE", "types": [{"code": "DOUBLE"}, {"code": "DOUBLE"}, {"code": "DOUBLE"}]}},
+            "ReferenceFrame_static_CreateRelative": {"id": 6, "parameters": [{"name": "reference", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}}], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "ReferenceFrame"}},
+            "Flight_get_GForce": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Flight"}}], "return_type": {"code": "FLOAT"}},
+            "Vessel_get_Name": {"id": 8, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}, "documentation": "<doc><summary>The name of the vessel.</summary></doc>"},
+            "Vessel_set_Name": {"id": 9, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "value", "type": {"code": "STRING"}}]},
+            "get_ActiveVessel": {"id": 10, "parameters": [], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}
+        },
+        "classes": {"Vessel": {"documentation": "<doc><summary>A vessel.</summary></doc>"}, "Flight": {}, "ReferenceFrame": {}},
+        "enumerations": {"VesselType": {"values": [{"name": "Ship", "value": 0}, {"name": "Station", "value": 1, "documentation": "<doc><summary>A space station.</summary></doc>"}]}}
+    }"#;
+
+    #[test]
+    fn test_same_code_as_templates() {
+        let content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        let mut output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+        output_structure.foreign_types.push(output::ForeignType {
+            service: "Drawing".to_string(),
+            module: "drawing".to_string(),
+            name: "Line".to_string(),
+        });
+
+        for (procedure_ids, call_helper) in [(false, false), (true, false), (false, true), (true, true)] {
+            let options = Options {
+                procedure_ids,
+                krpc_version: Some("0.5.4".to_string()),
+                call_helper,
+                ..Options::default()
+            };
+            assert_eq!(
+                render("SpaceCenter", &output_structure, &options).unwrap(),
+                writer::render("SpaceCenter", &output_structure, &options).unwrap());
+            assert_eq!(
+                render_modules("SpaceCenter", &output_structure, &options).unwrap(),
+                writer::render_modules("SpaceCenter", &output_structure, &options).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        let options = Options { snapshots: true, snapshot_serde: true, ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+
+        for procedure_ids in [false, true] {
+            let options = Options { procedure_ids, ..options.clone() };
+            let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+            assert_eq!(rendered, writer::render("SpaceCenter", &output_structure, &options).unwrap());
+            assert_eq!(
+                render_modules("SpaceCenter", &output_structure, &options).unwrap(),
+                writer::render_modules("SpaceCenter", &output_structure, &options).unwrap());
+            assert!(rendered.contains("#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]\npub struct VesselSnapshot {\n    pub r#type: Option<VesselType>,\n"));
+            assert!(rendered.contains("pub const G_FORCE: Self = Self(1 << 0);"));
+            assert!(rendered.contains("fields: FlightFields,\n    ) -> Result<FlightSnapshot, error::Error> {"));
+            assert!(rendered.contains("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]\npub enum VesselType"));
+            assert!(!rendered.contains("ReferenceFrameSnapshot"));
+            assert_eq!(rendered.contains("procedure_id: 7,"), procedure_ids);
+            assert!(rendered.contains("        if results.len() != expected {\n            return Err(error::Error::BatchResults {\n                expected,\n                found: results.len(),\n            });\n        }\n"));
+        }
+
+        // The snapshot and its mask live in the private class module
+        let files = render_modules("SpaceCenter", &output_structure, &options).unwrap();
+        let index = &files.iter().find(|(file_name, _)| file_name == "mod.rs").unwrap().1;
+        assert!(index.contains("pub use flight::Flight;\npub use flight::FlightSnapshot;\npub use flight::FlightFields;\n"));
+    }
+
+    #[test]
+    fn test_property_objects() {
+        let content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        let options = Options { property_objects: true, ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+
+        for procedure_ids in [false, true] {
+            let options = Options { procedure_ids, ..options.clone() };
+            let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+            assert_eq!(rendered, writer::render("SpaceCenter", &output_structure, &options).unwrap());
+            assert_eq!(
+                render_modules("SpaceCenter", &output_structure, &options).unwrap(),
+                writer::render_modules("SpaceCenter", &output_structure, &options).unwrap());
+            assert!(rendered.contains("use crate::property;"));
+            assert!(rendered.contains("/// The name of the vessel.\n    pub fn name(&'a self) -> property::Property<'a, String> {\n        property::Property::new(\n            self.conn,\n            Some(self),"));
+            assert!(rendered.contains("pub fn active_vessel(\n        &'a self,\n    ) -> property::Property<'a, Vessel<'a>, property::ReadOnly> {\n        property::Property::read_only(\n            self.conn,\n            None,"));
+            assert!(!rendered.contains("fn get_name("));
+            assert!(!rendered.contains("fn set_name("));
+            // Takes an argument besides the vessel, so it stays a method
+            assert!(rendered.contains("pub async fn get_position("));
+            assert_eq!(rendered.contains("(\"Vessel_set_Name\", 9),"), procedure_ids);
+            assert!(rendered.contains("(\"Vessel_set_Name\", \"Vessel_set_Name("));
+        }
+    }
+
+    #[test]
+    fn test_same_code_with_config() {
+        let config: Config = toml::from_str(r#"
+            [services.SpaceCenter.procedures.WarpTo]
+            rename = "warp"
+            attributes = ["must_use"]
+            parameters.ut.type = "crate::Time"
+            parameters.maxRailsRate.type = "crate::Rate"
+
+            [services.SpaceCenter.procedures.Vessel_get_Position]
+            return_type = "crate::Vector3"
+
+            [services.SpaceCenter.procedures.Vessel_get_Type]
+            return_type = "crate::Kind"
+
+            [services.SpaceCenter.classes.Vessel]
+            derives = ["Clone", "Copy"]
+            attributes = ["non_exhaustive"]
+
+            [services.SpaceCenter.enumerations.VesselType]
+            derives = ["PartialOrd"]
+            attributes = ["repr(i32)"]
+        "#).unwrap();
+        let mut content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        assert!(config.apply("SpaceCenter", &mut content).is_empty());
+        let output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+
+        let options = Options::default();
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert_eq!(rendered, writer::render("SpaceCenter", &output_structure, &options).unwrap());
+        assert!(rendered.contains("    #[must_use]\n    pub async fn warp(\n        &'a self,\n        ut: crate::Time,\n        args: WarpToArgs,\n"));
+        assert!(rendered.contains("value: Into::<f64>::into(ut).krpc_encode()?"));
+        assert!(rendered.contains("pub max_rails_rate: Option<crate::Rate>,"));
+        assert!(rendered.contains(") -> Result<crate::Vector3, error::Error> {"));
+        assert!(rendered.contains("Ok(return_value.into())"));
+        assert!(rendered.contains("#[non_exhaustive]\n#[derive(Debug, Clone, Copy)]\npub struct Vessel<'a>"));
+        assert!(rendered.contains("#[repr(i32)]\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd)]"));
+
+        let options = Options { call_helper: true, ..Options::default() };
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert_eq!(rendered, writer::render("SpaceCenter", &output_structure, &options).unwrap());
+        assert!(rendered.contains("pub const SERVICE: &str = \"SpaceCenter\";"));
+        assert!(rendered.contains(".call::<Flight<'a>>(SERVICE, \"Vessel_Flight\", (self, args.reference_frame))"));
+        assert!(rendered.contains(".call::<VesselType>(SERVICE, \"Vessel_get_Type\", (self,))\n            .await\n            .map(Into::into)"));
+    }
+
+    #[test]
+    fn test_keyword_identifiers() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "Loop": {"id": 1, "parameters": [{"name": "type", "type": {"code": "STRING"}}, {"name": "match", "type": {"code": "BOOL"}, "default_value": "AA=="}]},
+                "Vessel_get_Type": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let options = Options { snapshots: true, call_helper: true, ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+
+        for call_helper in [false, true] {
+            let options = Options { call_helper, ..options.clone() };
+            let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+            assert_eq!(rendered, writer::render("SpaceCenter", &output_structure, &options).unwrap());
+            assert!(rendered.contains("pub async fn r#loop("));
+            assert!(rendered.contains("r#type: String,"));
+            assert!(rendered.contains("pub r#match: Option<bool>,"));
+        }
+    }
+
+    #[test]
+    fn test_ident() {
+        assert_eq!(ident("vessel").unwrap().to_string(), "vessel");
+        assert_eq!(ident(&parser::raw_identifier("type".to_string())).unwrap().to_string(), "r#type");
+        for name in ["type", "self", "Self", "", "a b"] {
+            assert!(matches!(ident(name), Err(Error::Tokens(_))), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        let content: original::Content = serde_json::from_str(SCHEMA).unwrap();
+        let mut output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+        output_structure.classes.get_mut("Vessel").unwrap().attributes.push("must_use(".to_string());
+        assert!(matches!(render("SpaceCenter", &output_structure, &Options::default()), Err(Error::Tokens(message)) if message.contains("must_use(")));
+
+        let mut output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+        output_structure.methods[0].return_type = Some(serde_json::from_str(r#"{"code": "CLASS", "service": "SpaceCenter"}"#).unwrap());
+        assert!(matches!(render("SpaceCenter", &output_structure, &Options::default()), Err(Error::Tokens(message)) if message == "CLASS type without a name"));
+    }
+}
@@ -1,26 +1,799 @@
-use std::fs::File;
-use handlebars;
+use std::path::{Path, PathBuf};
+use convert_case::{Case, Casing};
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use regex::Regex;
 
+use crate::error::{Error, InvalidCode};
+use crate::features;
+use crate::format::{self, FormatError};
+use crate::original;
 use crate::output;
+use crate::options::{Backend, Layout, Options};
+use crate::parser;
+use crate::tokens;
 
-pub fn write_to_file(service_name: &str, path: &std::path::Path, output_structure: &output::OutputStructure) {
-    let mut handlebars = handlebars::Handlebars::new();
-    
-    let template_bytes = std::include_bytes!("../../templates/service.rs.hbs");
-    handlebars.register_template_string("template", String::from_utf8_lossy(template_bytes)).unwrap();
+const TEMPLATES: &[(&str, &str)] = &[
+    ("service", include_str!("../../templates/service.rs.hbs")),
+    ("server", include_str!("../../templates/server.rs.hbs")),
+    ("class_file", include_str!("../../templates/class_file.rs.hbs")),
+    ("enums_file", include_str!("../../templates/enums_file.rs.hbs")),
+];
 
-    let mut output_file = File::create(path).unwrap();
+const PARTIALS: &[(&str, &str)] = &[
+    ("method", include_str!("../../templates/partials/method.rs.hbs")),
+    ("class_header", include_str!("../../templates/partials/class_header.rs.hbs")),
+    ("enum", include_str!("../../templates/partials/enum.rs.hbs")),
+    ("class", include_str!("../../templates/partials/class.rs.hbs")),
+    ("property", include_str!("../../templates/partials/property.rs.hbs")),
+];
 
+/// Create the handlebars registry with the built-in templates, partials and
+/// helpers. Files in `template_dir` named like the built-in ones, e.g.
+/// `service.rs.hbs` or `partials/method.rs.hbs`, replace them.
+fn create_registry(template_dir: Option<&Path>) -> Result<Handlebars<'static>, Error> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    for (name, template) in TEMPLATES {
+        let file_name = format!("{}.rs.hbs", name);
+        let template = read_override(template_dir, &file_name)?.unwrap_or_else(|| template.to_string());
+        handlebars.register_template_string(name, template)
+            .map_err(|e| Error::Template(file_name, e.to_string()))?;
+    }
+    for (name, partial) in PARTIALS {
+        let file_name = format!("partials/{}.rs.hbs", name);
+        let partial = read_override(template_dir, &file_name)?.unwrap_or_else(|| partial.to_string());
+        handlebars.register_partial(name, partial)
+            .map_err(|e| Error::Template(file_name, e.to_string()))?;
+    }
+
+    handlebars.register_helper("snake_case", Box::new(snake_case_helper));
+    handlebars.register_helper("pascal_case", Box::new(pascal_case_helper));
+    handlebars.register_helper("doc", Box::new(doc_helper));
+    handlebars.register_helper("rust_type", Box::new(rust_type_helper));
+    Ok(handlebars)
+}
+
+/// Render one of the registered templates, turning errors such as a missing
+/// variable in a strict template or a failing helper into `Error::Template`
+fn render_template(handlebars: &Handlebars, name: &str, data: &serde_json::Map<String, serde_json::Value>) -> Result<String, Error> {
+    handlebars.render(name, data)
+        .map_err(|e| Error::Template(format!("{}.rs.hbs", name), e.to_string()))
+}
+
+fn read_override(template_dir: Option<&Path>, file_name: &str) -> Result<Option<String>, Error> {
+    let path = match template_dir {
+        Some(template_dir) => template_dir.join(file_name),
+        None => return Ok(None),
+    };
+    if path.exists() {
+        std::fs::read_to_string(path)
+            .map(Some)
+            .map_err(|e| Error::Template(file_name.to_string(), e.to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn string_param<'a>(helper: &'a Helper, name: &str) -> Result<&'a str, RenderError> {
+    helper.param(0)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new(format!("{} expects a string parameter", name)))
+}
+
+/// `{{snake_case "MeanAltitude"}}` renders `mean_altitude`
+fn snake_case_helper(helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    out.write(&string_param(helper, "snake_case")?.to_case(Case::Snake))?;
+    Ok(())
+}
+
+/// `{{pascal_case "mean_altitude"}}` renders `MeanAltitude`
+fn pascal_case_helper(helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    out.write(&string_param(helper, "pascal_case")?.to_case(Case::Pascal))?;
+    Ok(())
+}
+
+/// `{{doc documentation indent=4}}` renders kRPC's XML documentation as `///`
+/// comment lines, indenting every line but the first by `indent` spaces
+fn doc_helper(helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let documentation = string_param(helper, "doc")?;
+    let indent = helper.hash_get("indent")
+        .and_then(|indent| indent.value().as_u64())
+        .unwrap_or(0) as usize;
+    let separator = format!("\n{}", " ".repeat(indent));
+    out.write(&doc_lines(documentation).join(&separator))?;
+    Ok(())
+}
+
+/// `{{rust_type method.return_type lifetime="'a"}}` renders the Rust type of
+/// a kRPC type
+fn rust_type_helper(helper: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> HelperResult {
+    let value = helper.param(0)
+        .map(|param| param.value().clone())
+        .ok_or_else(|| RenderError::new("rust_type expects a type parameter"))?;
+    let lifetime = helper.hash_get("lifetime")
+        .and_then(|lifetime| lifetime.value().as_str())
+        .unwrap_or("'_");
+    let rust_type = match serde_json::from_value::<Option<original::Type>>(value) {
+        Ok(Some(r#type)) => parser::rust_type(&r#type, lifetime),
+        Ok(None) => "()".to_string(),
+        Err(e) => return Err(RenderError::new(format!("rust_type expects a type parameter: {}", e))),
+    };
+    out.write(&rust_type)?;
+    Ok(())
+}
+
+pub fn doc_lines(documentation: &str) -> Vec<String> {
+    let replacements = [
+        // <see cref="M:SpaceCenter.Vessel.Flight" /> refers to Vessel.Flight
+        (r#"<see cref="[A-Z]:[^."]+\.([^"]+)"\s*/>"#, "`$1`"),
+        (r#"<paramref name="([^"]+)"\s*/>"#, "`$1`"),
+        (r"</?c>", "`"),
+        (r#"<param name="([^"]+)">"#, "\n\n* `$1`: "),
+        (r"<returns>", "\n\nReturns: "),
+        (r"<remarks>", "\n\n"),
+        (r"<[^>]+>", ""),
+    ];
+    let mut text = documentation.to_string();
+    for (pattern, replacement) in replacements.iter() {
+        text = Regex::new(pattern).unwrap().replace_all(&text, *replacement).to_string();
+    }
+    let text = text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() && lines.last().is_none_or(|last| last == "///") {
+            continue;
+        }
+        lines.push(if line.is_empty() { "///".to_string() } else { format!("/// {}", line) });
+    }
+    while lines.last().is_some_and(|last| last == "///") {
+        lines.pop();
+    }
+    lines
+}
+
+/// First line of every built-in template, used to recognize generated files
+pub const GENERATED_HEADER: &str = "// Generated file DO NOT EDIT";
+
+/// Replace the file at `path` with `contents` unless it already has exactly
+/// that content, so unchanged bindings keep their mtime and don't trigger a
+/// rebuild. The new content is written to a temporary file next to `path`
+/// and renamed over it, so readers never see a partially written file.
+/// Returns whether the file was written.
+pub fn write_if_changed(path: &Path, contents: &str) -> std::io::Result<bool> {
+    match std::fs::read(path) {
+        Ok(existing) if existing == contents.as_bytes() => return Ok(false),
+        Ok(_) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e),
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name));
+    std::fs::write(&temporary, contents)?;
+    if let Err(e) = std::fs::rename(&temporary, path) {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(e);
+    }
+    Ok(true)
+}
+
+/// Delete the generated service files and service directories in
+/// `directory` that are not in `keep`. Only top-level `.rs` files starting
+/// with the generated-file header count as service files, and only
+/// subdirectories holding a generated service module in `mod.rs` as service
+/// directories; of these the generated files not in `keep` are removed, and
+/// the directory too if this leaves it empty. Other subdirectories are left
+/// alone. Returns whether anything was removed.
+pub fn remove_stale_files(directory: &Path, keep: &[PathBuf]) -> std::io::Result<bool> {
+    let mut removed = false;
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            removed |= remove_if_stale(&path, keep)?;
+            continue;
+        }
+        let kept = keep.iter().any(|kept| kept.starts_with(&path));
+        if !kept && !is_service_module(&path.join("mod.rs")) {
+            continue;
+        }
+        let mut removed_here = false;
+        for entry in std::fs::read_dir(&path)? {
+            let path = entry?.path();
+            if path.is_file() {
+                removed_here |= remove_if_stale(&path, keep)?;
+            }
+        }
+        if removed_here {
+            removed = true;
+            // Fails and leaves the directory alone unless it only held
+            // generated files
+            let _ = std::fs::remove_dir(&path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove `path` if it is a generated `.rs` file not in `keep`
+fn remove_if_stale(path: &Path, keep: &[PathBuf]) -> std::io::Result<bool> {
+    if path.extension().is_none_or(|extension| extension != "rs") || keep.iter().any(|kept| kept == path) {
+        return Ok(false);
+    }
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    if !contents.starts_with(GENERATED_HEADER) {
+        return Ok(false);
+    }
+    std::fs::remove_file(path)?;
+    Ok(true)
+}
+
+/// Whether `path` is the generated `mod.rs` of a service directory, which
+/// unlike the `mod.rs` of `Options::features` pins its schema
+fn is_service_module(path: &Path) -> bool {
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    contents.starts_with(GENERATED_HEADER) && contents.contains("pub const SCHEMA_HASH")
+}
+
+/// Where the client bindings of a service go in `directory`:
+/// `space_center.rs`, or the `space_center` directory with `Layout::Modules`
+pub fn service_path(directory: &Path, service_name: &str, layout: Layout) -> PathBuf {
+    let module = features::service_module(service_name);
+    match layout {
+        Layout::File => directory.join(format!("{}.rs", module)),
+        Layout::Modules => directory.join(module),
+    }
+}
+
+/// Render the client bindings of a service with the paths they are written
+/// to: `path` itself, or with `Layout::Modules` the files in the service
+/// directory `path`
+pub fn render_files(service_name: &str, path: &Path, output_structure: &output::OutputStructure, options: &Options) -> Result<Vec<(PathBuf, String)>, Error> {
+    match options.layout {
+        Layout::File => Ok(vec![(path.to_path_buf(), render(service_name, output_structure, options)?)]),
+        Layout::Modules => Ok(render_modules(service_name, output_structure, options)?.into_iter()
+            .map(|(file_name, source)| (path.join(file_name), source))
+            .collect()),
+    }
+}
+
+/// Module and file name of a class with `Layout::Modules`: `Vessel` lives in
+/// `mod vessel`, declared in `mod.rs` and read from `vessel.rs`. Keywords
+/// become raw identifiers, and names taken by the imports of `mod.rs` get a
+/// `_class` suffix.
+pub fn class_module(class_name: &str) -> (String, String) {
+    const TAKEN: &[&str] = &["connection", "decoder", "encoder", "error", "schema", "enums", "mod"];
+    let mut file = class_name.to_case(Case::Snake);
+    if TAKEN.contains(&file.as_str()) {
+        file.push_str("_class");
+    }
+    (parser::raw_identifier(file.clone()), format!("{}.rs", file))
+}
+
+fn service_data(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> serde_json::Map<String, serde_json::Value> {
     let mut data = serde_json::Map::<String, serde_json::Value>::new();
     data.insert("service_name".to_string(), handlebars::to_json(service_name));
+    data.insert("service_id".to_string(), handlebars::to_json(output_structure.service_id));
+    data.insert("documentation".to_string(), handlebars::to_json(&output_structure.documentation));
+    data.insert("procedure_ids".to_string(), handlebars::to_json(options.procedure_ids));
+    data.insert("call_helper".to_string(), handlebars::to_json(options.call_helper));
+    data.insert("snapshot_serde".to_string(), handlebars::to_json(options.snapshot_serde));
+    data.insert("property_objects".to_string(), handlebars::to_json(options.property_objects));
+    data.insert("schema_hash".to_string(), handlebars::to_json(&output_structure.schema_hash));
+    data.insert("procedure_names".to_string(), handlebars::to_json(&output_structure.procedure_names));
+    data.insert("krpc_version".to_string(), handlebars::to_json(&options.krpc_version));
 
     data.insert("service_methods".to_string(), handlebars::to_json(&output_structure.methods));
     data.insert("service_getters_setters".to_string(), handlebars::to_json(&output_structure.getters_setters));
+    data.insert("service_properties".to_string(), handlebars::to_json(&output_structure.properties));
     data.insert("classes".to_string(), handlebars::to_json(&output_structure.classes));
     data.insert("enumerations".to_string(), handlebars::to_json(&output_structure.enumerations));
+    data.insert("arguments_structs".to_string(), handlebars::to_json(&output_structure.arguments_structs));
+    data.insert("foreign_types".to_string(), handlebars::to_json(&output_structure.foreign_types));
+
+    data.insert("modules".to_string(), handlebars::to_json(options.layout == Layout::Modules));
+    let class_modules: Vec<serde_json::Value> = output_structure.classes.values()
+        .map(|class| {
+            let (module, file) = class_module(&class.name);
+            let snapshot = class.snapshot.as_ref().map(|snapshot| serde_json::json!({"name": snapshot.name, "mask": snapshot.mask}));
+            serde_json::json!({"module": module, "file": file, "name": class.name, "snapshot": snapshot})
+        })
+        .collect();
+    data.insert("class_modules".to_string(), serde_json::Value::Array(class_modules));
+    data
+}
+
+/// Render the client bindings of a service as one file. With
+/// `Layout::Modules` this is the `mod.rs` of the service directory.
+pub fn render(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<String, Error> {
+    if options.backend == Backend::Tokens {
+        return tokens::render(service_name, output_structure, options);
+    }
+    let handlebars = create_registry(options.template_dir.as_deref())?;
+    let data = service_data(service_name, output_structure, options);
+    let source = render_template(&handlebars, "service", &data)?;
+    format_source(&handlebars, &data, &source, "service.rs.hbs", service_name, output_structure, options)
+}
+
+/// Render the files of the service directory for `Layout::Modules`, named
+/// relative to it: `mod.rs`, a file per class and `enums.rs`
+pub fn render_modules(service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<Vec<(String, String)>, Error> {
+    if options.backend == Backend::Tokens {
+        return tokens::render_modules(service_name, output_structure, options);
+    }
+    let options = &Options { layout: Layout::Modules, ..options.clone() };
+    let mut files = vec![("mod.rs".to_string(), render(service_name, output_structure, options)?)];
+
+    let handlebars = create_registry(options.template_dir.as_deref())?;
+    let mut data = serde_json::Map::<String, serde_json::Value>::new();
+    data.insert("service_name".to_string(), handlebars::to_json(service_name));
+    data.insert("procedure_ids".to_string(), handlebars::to_json(options.procedure_ids));
+    data.insert("call_helper".to_string(), handlebars::to_json(options.call_helper));
+    data.insert("snapshot_serde".to_string(), handlebars::to_json(options.snapshot_serde));
+    data.insert("property_objects".to_string(), handlebars::to_json(options.property_objects));
+    for class in output_structure.classes.values() {
+        data.insert("class".to_string(), handlebars::to_json(class));
+        let source = render_template(&handlebars, "class_file", &data)?;
+        let source = format_source(&handlebars, &data, &source, "class_file.rs.hbs", service_name, output_structure, options)?;
+        files.push((class_module(&class.name).1, source));
+    }
+    if !output_structure.enumerations.is_empty() {
+        data.remove("class");
+        data.insert("enumerations".to_string(), handlebars::to_json(&output_structure.enumerations));
+        let source = render_template(&handlebars, "enums_file", &data)?;
+        files.push(("enums.rs".to_string(), format_source(&handlebars, &data, &source, "enums_file.rs.hbs", service_name, output_structure, options)?));
+    }
+    Ok(files)
+}
+
+/// Format rendered code, pointing parse errors at the procedure and the
+/// template line that produced them
+fn format_source(handlebars: &Handlebars, data: &serde_json::Map<String, serde_json::Value>, source: &str, template: &str, service_name: &str, output_structure: &output::OutputStructure, options: &Options) -> Result<String, Error> {
+    format::format(source, options.format).map_err(|e| match e {
+        FormatError::Parse { line, column, message } => {
+            let (procedure, template, template_line) = match locate_in_methods(handlebars, data, output_structure, options) {
+                Some((procedure, template_line)) => (Some(procedure), "partials/method.rs.hbs", template_line),
+                None => (None, template, find_template_line(&template_source(options, template), source, line)),
+            };
+            Error::InvalidCode(Box::new(InvalidCode {
+                service: service_name.to_string(),
+                line,
+                column,
+                message,
+                procedure,
+                template: template.to_string(),
+                template_line,
+            }))
+        },
+        FormatError::Rustfmt(message) => Error::Rustfmt(message),
+    })
+}
+
+/// Render every method on its own to find the one whose code does not
+/// parse. Returns its procedure and the line of the method partial.
+fn locate_in_methods(handlebars: &Handlebars, data: &serde_json::Map<String, serde_json::Value>, output_structure: &output::OutputStructure, options: &Options) -> Option<(String, Option<usize>)> {
+    let methods = output_structure.methods.iter()
+        .chain(&output_structure.getters_setters)
+        .chain(output_structure.classes.values().flat_map(|class| class.methods.iter().chain(&class.getters_setters).chain(&class.static_methods)));
+
+    for method in methods {
+        let mut data = data.clone();
+        data.insert("methods".to_string(), handlebars::to_json(vec![method]));
+        let rendered = handlebars.render_template("impl Method {\n{{#each methods}}{{> method conn=\"self.conn\"}}{{/each}}}\n", &data).ok()?;
+        if let Err(e) = syn::parse_file(&rendered) {
+            // The first rendered line is the `impl` wrapper
+            let template = format!("impl Method {{\n{}", template_source(options, "partials/method.rs.hbs"));
+            let template_line = find_template_line(&template, &rendered, e.span().start().line)
+                .and_then(|line| line.checked_sub(1));
+            return Some((method.procedure.clone(), template_line));
+        }
+    }
+    None
+}
+
+fn template_source(options: &Options, file_name: &str) -> String {
+    let template_dir = options.template_dir.as_deref();
+    TEMPLATES.iter()
+        .map(|(name, template)| (format!("{}.rs.hbs", name), template))
+        .chain(PARTIALS.iter().map(|(name, partial)| (format!("partials/{}.rs.hbs", name), partial)))
+        .find(|(name, _)| name == file_name)
+        .map(|(_, template)| read_override(template_dir, file_name).ok().flatten().unwrap_or_else(|| template.to_string()))
+        .unwrap_or_default()
+}
+
+/// Find the 1-based line of `template` that produced line `line` of
+/// `rendered`. Rendered lines are matched to template lines in order, treating
+/// every `{{...}}` expression as a wildcard and going back to the start of
+/// the template when a loop repeats. Lines holding only block helpers are
+/// skipped.
+fn find_template_line(template: &str, rendered: &str, line: usize) -> Option<usize> {
+    let expression = Regex::new(r"\{\{\{?[^}]*\}\}\}?").unwrap();
+    let patterns: Vec<(usize, Regex)> = template.lines().enumerate()
+        .filter(|(_, line)| !expression.replace_all(line, "").trim().is_empty())
+        .filter_map(|(index, line)| {
+            let pattern = expression.split(line.trim())
+                .map(regex::escape)
+                .collect::<Vec<String>>()
+                .join(".*");
+            Regex::new(&format!("^{}$", pattern)).ok().map(|pattern| (index + 1, pattern))
+        })
+        .collect();
+
+    let mut position = 0;
+    let mut found = None;
+    for rendered_line in rendered.lines().take(line) {
+        let rendered_line = rendered_line.trim();
+        let next = (position..patterns.len()).chain(0..position)
+            .find(|&candidate| patterns[candidate].1.is_match(rendered_line));
+        if let Some(next) = next {
+            position = next + 1;
+            found = Some(patterns[next].0);
+        } else {
+            found = None;
+        }
+    }
+    found
+}
+
+pub fn render_server(service_name: &str, server_structure: &output::ServerStructure, options: &Options) -> Result<String, Error> {
+    let handlebars = create_registry(options.template_dir.as_deref())?;
+
+    let mut data = serde_json::Map::<String, serde_json::Value>::new();
+    data.insert("service_name".to_string(), handlebars::to_json(service_name));
+
+    data.insert("procedures".to_string(), handlebars::to_json(&server_structure.procedures));
+    data.insert("classes".to_string(), handlebars::to_json(&server_structure.classes));
+    data.insert("enumerations".to_string(), handlebars::to_json(&server_structure.enumerations));
+
+    let source = render_template(&handlebars, "server", &data)?;
+    format::format(&source, options.format).map_err(|e| match e {
+        FormatError::Parse { line, column, message } => Error::InvalidCode(Box::new(InvalidCode {
+            service: service_name.to_string(),
+            line,
+            column,
+            message,
+            procedure: None,
+            template: "server.rs.hbs".to_string(),
+            template_line: find_template_line(&template_source(options, "server.rs.hbs"), &source, line),
+        })),
+        FormatError::Rustfmt(message) => Error::Rustfmt(message),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_if_changed() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_write_if_changed_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("space_center.rs");
+
+        assert!(write_if_changed(&path, "pub struct SpaceCenter;\n").unwrap());
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert!(!write_if_changed(&path, "pub struct SpaceCenter;\n").unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        assert!(write_if_changed(&path, "pub struct SpaceCenter<'a>;\n").unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "pub struct SpaceCenter<'a>;\n");
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_remove_stale_files() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_remove_stale_files_{}", std::process::id()));
+        let generated = |name: &str| format!("{}\npub const SCHEMA_HASH: u64 = 0;\npub struct {};\n", GENERATED_HEADER, name);
+        let service_dir = directory.join("space_center");
+        let stale_dir = directory.join("drawing");
+        let sibling_dir = directory.join("bindings");
+        for dir in &[&service_dir, &stale_dir, &sibling_dir] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(directory.join("krpc.rs"), generated("KRPC")).unwrap();
+        std::fs::write(directory.join("ui.rs"), generated("UI")).unwrap();
+        std::fs::write(directory.join("main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(service_dir.join("mod.rs"), generated("SpaceCenter")).unwrap();
+        std::fs::write(service_dir.join("part.rs"), format!("{}\npub struct Part;\n", GENERATED_HEADER)).unwrap();
+        std::fs::write(stale_dir.join("mod.rs"), generated("Drawing")).unwrap();
+        std::fs::write(stale_dir.join("line.rs"), format!("{}\npub struct Line;\n", GENERATED_HEADER)).unwrap();
+        std::fs::write(sibling_dir.join("mod.rs"), format!("{}\npub mod other;\n", GENERATED_HEADER)).unwrap();
+        std::fs::write(sibling_dir.join("other.rs"), generated("Other")).unwrap();
+
+        let keep = vec![directory.join("krpc.rs"), service_dir.join("mod.rs")];
+        assert!(remove_stale_files(&directory, &keep).unwrap());
+        assert!(directory.join("krpc.rs").exists());
+        assert!(!directory.join("ui.rs").exists());
+        assert!(directory.join("main.rs").exists());
+        assert!(service_dir.join("mod.rs").exists());
+        assert!(!service_dir.join("part.rs").exists());
+        assert!(!stale_dir.exists());
+        // An unrelated generated module in a sibling subdirectory is not a
+        // service directory of this run
+        assert!(sibling_dir.join("mod.rs").exists());
+        assert!(sibling_dir.join("other.rs").exists());
+        assert!(!remove_stale_files(&directory, &keep).unwrap());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "Vessel_get_Name": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let mut output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+        assert!(render("SpaceCenter", &output_structure, &Options::default()).is_ok());
+
+        output_structure.classes.get_mut("Vessel").unwrap().getters_setters[0].return_value = "(/*list*/".to_string();
+        match render("SpaceCenter", &output_structure, &Options::default()) {
+            Err(Error::InvalidCode(e)) => {
+                assert_eq!(e.procedure.as_deref(), Some("Vessel_get_Name"));
+                assert_eq!(e.template, "partials/method.rs.hbs");
+                // The unclosed parenthesis is detected at the closing brace of the method
+                let lines: Vec<&str> = PARTIALS[0].1.lines().collect();
+                let return_line = lines.iter().position(|line| line.contains("Ok({{{ return_value }}})")).unwrap();
+                let closing_line = (return_line..lines.len()).find(|&index| lines[index] == "    }").unwrap();
+                assert_eq!(e.template_line, Some(closing_line + 1));
+            },
+            result => panic!("expected invalid code, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_schema_pinning() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {"get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}}},
+            "classes": {},
+            "enumerations": {}
+        }"#).unwrap();
+        let options = Options { krpc_version: Some("0.5.2".to_string()), ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert!(rendered.contains("pub const KRPC_VERSION: Option<&str> = Some(\"0.5.2\");"));
+        // A different version is a mismatch even if every signature matches
+        assert!(rendered.contains("let version_matches = KRPC_VERSION.map_or(true, |version| version == found_version);"));
+        assert!(rendered.contains("if version_matches && schema_hash(signatures) == SCHEMA_HASH {"));
+        assert!(rendered.contains("if version_matches && missing.is_empty() && changed.is_empty() {"));
+    }
+
+    #[test]
+    fn test_call_helper() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "WarpTo": {"id": 1, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}, {"name": "maxRate", "type": {"code": "FLOAT"}, "default_value": "AADIQg=="}]},
+                "Vessel_get_Name": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let options = Options { call_helper: true, ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert!(rendered.contains("pub const SERVICE: &str = \"SpaceCenter\";"));
+        // Optional arguments are passed as `Option`s at their position
+        assert!(rendered.contains("        self.conn.call::<()>(SERVICE, \"WarpTo\", (ut, args.max_rate)).await\n    }"));
+        assert!(rendered.contains("        self.conn.call::<String>(SERVICE, \"Vessel_get_Name\", (self,)).await\n    }"));
+        assert!(!rendered.contains("execute_procedure(\"SpaceCenter\""));
+        assert!(!rendered.contains("let mut arguments"));
+    }
+
+    #[test]
+    fn test_procedure_ids() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "get_UT": {"id": 1, "parameters": [], "return_type": {"code": "DOUBLE"}},
+                "Vessel_get_Name": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}},
+            "enumerations": {}
+        }"#).unwrap();
+        let options = Options { procedure_ids: true, snapshots: true, ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert!(rendered.contains("pub const SERVICE_ID: u32 = 2;"));
+        assert!(rendered.contains("pub const PROCEDURE_IDS: &[(&str, u32)] = &[(\"get_UT\", 1), (\"Vessel_get_Name\", 7)];"));
+        assert!(rendered.contains(".execute_procedure_by_id(SERVICE_ID, 1, arguments)"));
+        assert!(rendered.contains("service_id: SERVICE_ID,"));
+        assert!(rendered.contains("procedure_id: 7,"));
+        assert!(!rendered.contains("execute_procedure(\"SpaceCenter\""));
+        // GetServices carries no ids, the server is only checked for the same schema
+        assert!(!rendered.contains("ProcedureIdMismatch"));
+        // Both checks share one round trip to the server
+        assert_eq!(rendered.matches("\"GetServices\"").count(), 1);
+        assert!(rendered.contains("let (version, service) = self.server_schema().await?;"));
+        assert!(rendered.contains("pub const SCHEMA_PROCEDURES: &[&str] = &[\"Vessel_get_Name\", \"get_UT\"];"));
+        assert!(rendered.contains("mismatch.added = added;"));
+
+        let options = Options { call_helper: true, ..options };
+        let rendered = render("SpaceCenter", &output_structure, &options).unwrap();
+        assert!(rendered.contains(".call_by_id::<f64>(SERVICE_ID, 1, ())"));
+        assert!(rendered.contains(".call_by_id::<String>(SERVICE_ID, 7, (self,))"));
+    }
+
+    #[test]
+    fn test_render_modules() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "get_ActiveVessel": {"id": 1, "parameters": [], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                "Vessel_get_Type": {"id": 2, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "VesselType"}}
+            },
+            "classes": {"Vessel": {}, "Type": {}},
+            "enumerations": {"VesselType": {"values": [{"name": "Ship", "value": 0}]}}
+        }"#).unwrap();
+        let output_structure = parser::create_output_structure(&content, &Options::default()).unwrap();
+        let files = render_modules("SpaceCenter", &output_structure, &Options::default()).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["mod.rs", "type.rs", "vessel.rs", "enums.rs"]);
+
+        let service = &files[0].1;
+        assert!(service.contains("mod r#type;\npub use r#type::Type;\n"));
+        assert!(service.contains("mod vessel;\npub use vessel::Vessel;\n"));
+        assert!(service.contains("pub use enums::*;"));
+        assert!(!service.contains("pub struct Vessel"));
+        assert!(files[2].1.contains("use super::*;\n"));
+        assert!(files[2].1.contains("pub struct Vessel<'a> {\n    pub(crate) id: u64,\n"));
+        assert!(files[3].1.contains("pub enum VesselType"));
+
+        assert_eq!(class_module("ReferenceFrame"), ("reference_frame".to_string(), "reference_frame.rs".to_string()));
+        assert_eq!(class_module("Error"), ("error_class".to_string(), "error_class.rs".to_string()));
+    }
+
+    /// Public items reachable from the service module in `file`, with the
+    /// public methods and constants of their impls as `Type::name`. `mod`
+    /// declarations are looked up in `files`.
+    fn public_paths(file: &str, files: &[(String, String)]) -> std::collections::BTreeSet<String> {
+        use syn::ext::IdentExt;
+        let is_public = |visibility: &syn::Visibility| matches!(visibility, syn::Visibility::Public(_));
+        let mut paths = std::collections::BTreeSet::new();
+        for item in syn::parse_file(file).unwrap().items {
+            match item {
+                syn::Item::Struct(item) if is_public(&item.vis) => { paths.insert(item.ident.to_string()); },
+                syn::Item::Enum(item) if is_public(&item.vis) => { paths.insert(item.ident.to_string()); },
+                syn::Item::Const(item) if is_public(&item.vis) => { paths.insert(item.ident.to_string()); },
+                syn::Item::Fn(item) if is_public(&item.vis) => { paths.insert(item.sig.ident.to_string()); },
+                syn::Item::Impl(item) => {
+                    let self_type = match &*item.self_ty {
+                        syn::Type::Path(path) => path.path.segments.last().unwrap().ident.to_string(),
+                        _ => continue,
+                    };
+                    for impl_item in item.items {
+                        match impl_item {
+                            syn::ImplItem::Fn(method) if is_public(&method.vis) => { paths.insert(format!("{}::{}", self_type, method.sig.ident)); },
+                            syn::ImplItem::Const(constant) if is_public(&constant.vis) => { paths.insert(format!("{}::{}", self_type, constant.ident)); },
+                            _ => {},
+                        }
+                    }
+                },
+                syn::Item::Use(item) if is_public(&item.vis) => {
+                    let (module, name) = match &item.tree {
+                        syn::UseTree::Path(path) => match &*path.tree {
+                            syn::UseTree::Name(name) => (path.ident.unraw().to_string(), Some(name.ident.to_string())),
+                            syn::UseTree::Glob(_) => (path.ident.unraw().to_string(), None),
+                            tree => panic!("unexpected use {}", quote::quote!(#tree)),
+                        },
+                        tree => panic!("unexpected use {}", quote::quote!(#tree)),
+                    };
+                    let source = &files.iter().find(|(file_name, _)| *file_name == format!("{}.rs", module)).unwrap().1;
+                    paths.extend(public_paths(source, files).into_iter().filter(|path| match &name {
+                        Some(name) => path == name || path.starts_with(&format!("{}::", name)),
+                        None => true,
+                    }));
+                },
+                _ => {},
+            }
+        }
+        paths
+    }
+
+    #[test]
+    fn test_modules_public_paths() {
+        let content: original::Content = serde_json::from_str(r#"{
+            "id": 2,
+            "procedures": {
+                "WarpTo": {"id": 1, "parameters": [{"name": "ut", "type": {"code": "DOUBLE"}}, {"name": "maxRailsRate", "type": {"code": "FLOAT"}, "default_value": "AACAPw=="}]},
+                "get_ActiveVessel": {"id": 2, "parameters": [], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}},
+                "Vessel_get_Type": {"id": 3, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "ENUMERATION", "service": "SpaceCenter", "name": "VesselType"}},
+                "Vessel_get_Name": {"id": 4, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}], "return_type": {"code": "STRING"}},
+                "Vessel_set_Name": {"id": 5, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Vessel"}}, {"name": "value", "type": {"code": "STRING"}}]},
+                "Type_static_Create": {"id": 6, "parameters": [], "return_type": {"code": "CLASS", "service": "SpaceCenter", "name": "Type"}},
+                "Error_get_Message": {"id": 7, "parameters": [{"name": "this", "type": {"code": "CLASS", "service": "SpaceCenter", "name": "Error"}}], "return_type": {"code": "STRING"}}
+            },
+            "classes": {"Vessel": {}, "Type": {}, "Error": {}},
+            "enumerations": {"VesselType": {"values": [{"name": "Ship", "value": 0}, {"name": "Station", "value": 1}]}}
+        }"#).unwrap();
+
+        for backend in [Backend::Handlebars, Backend::Tokens] {
+            let options = Options { backend, snapshots: true, procedure_ids: true, ..Options::default() };
+            let output_structure = parser::create_output_structure(&content, &options).unwrap();
+            let single = render("SpaceCenter", &output_structure, &options).unwrap();
+            let files = render_modules("SpaceCenter", &output_structure, &options).unwrap();
+            let paths = public_paths(&single, &[]);
+            assert!(paths.contains("Vessel::get_name"));
+            assert!(paths.contains("VesselFields::NAME"));
+            assert!(paths.contains("Type::create"));
+            assert!(paths.contains("Error::get_message"));
+            assert_eq!(public_paths(&files[0].1, &files), paths);
+        }
+    }
+
+    #[test]
+    fn test_doc_lines() {
+        let documentation = r#"<doc>
+<summary>
+Returns a <see cref="T:SpaceCenter.Flight" /> object in the given <paramref name="referenceFrame" />.
+</summary>
+<param name="referenceFrame">Reference frame. Defaults to the vessel's surface reference frame.</param>
+<returns>The flight, or <c>null</c>.</returns>
+</doc>"#;
+        assert_eq!(doc_lines(documentation), vec![
+            "/// Returns a `Flight` object in the given `referenceFrame`.",
+            "///",
+            "/// * `referenceFrame`: Reference frame. Defaults to the vessel's surface reference frame.",
+            "///",
+            "/// Returns: The flight, or `null`.",
+        ]);
+        assert!(doc_lines("").is_empty());
+    }
+
+    #[test]
+    fn test_template_override() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_template_override_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("partials")).unwrap();
+        std::fs::write(directory.join("partials/enum.rs.hbs"), "enum {{pascal_case name}} {}").unwrap();
+
+        let handlebars = create_registry(Some(&directory)).unwrap();
+        let data = serde_json::json!({"enumerations": [{"name": "vessel_type", "values": []}]});
+        let rendered = handlebars.render_template("{{#each enumerations}}{{> enum}}{{/each}} {{snake_case \"MeanAltitude\"}}", &data).unwrap();
+        assert_eq!(rendered, "enum VesselType {} mean_altitude");
+
+        let r#type = serde_json::json!({"code": "LIST", "types": [{"code": "CLASS", "service": "SpaceCenter", "name": "Part"}]});
+        let rendered = handlebars.render_template("{{rust_type this lifetime=\"'a\"}}", &r#type).unwrap();
+        assert_eq!(rendered, "Vec<Part<'a>>");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_invalid_template_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("partials")).unwrap();
+        std::fs::write(directory.join("partials/method.rs.hbs"), "{{#if x}}").unwrap();
+
+        match create_registry(Some(&directory)) {
+            Err(Error::Template(template, _)) => assert_eq!(template, "partials/method.rs.hbs"),
+            other => panic!("expected a template error, got {:?}", other.err()),
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_render_error() {
+        let directory = std::env::temp_dir().join(format!("krpc_gen_test_render_error_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("service.rs.hbs"), "{{snake_case service_id}}").unwrap();
 
-    handlebars.render_to_write("template", &data, &mut output_file).unwrap();
-    
-    // let mut output_test_file = File::create("output/generated.rs").unwrap();
-    // handlebars.render_to_write("template", &data, &mut output_test_file).unwrap();
+        let content: original::Content = serde_json::from_str(r#"{"id": 2, "procedures": {}, "classes": {}, "enumerations": {}}"#).unwrap();
+        let options = Options { template_dir: Some(directory.clone()), ..Options::default() };
+        let output_structure = parser::create_output_structure(&content, &options).unwrap();
+        match render("SpaceCenter", &output_structure, &options) {
+            Err(Error::Template(template, message)) => {
+                assert_eq!(template, "service.rs.hbs");
+                assert!(message.contains("snake_case expects a string parameter"));
+            },
+            other => panic!("expected a template error, got {:?}", other),
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 }
@@ -1,8 +1,171 @@
-use krpc_gen;
+use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(about = "Generate Rust code for kRPC")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate bindings from a schema file
+    Generate {
+        schema: PathBuf,
+        output: PathBuf,
+        /// Generate server-side service traits instead of client bindings
+        #[arg(long)]
+        server: bool,
+        /// Call procedures by numeric id instead of by name
+        #[arg(long)]
+        procedure_ids: bool,
+        /// kRPC version the schema comes from, recorded in the generated code
+        #[arg(long)]
+        krpc_version: Option<String>,
+        /// Directory with templates and partials overriding the built-in ones
+        #[arg(long)]
+        template_dir: Option<PathBuf>,
+        /// Configuration file with per-procedure rules, `krpc-gen.toml` in
+        /// the current directory if it exists
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Only generate procedures matching `Service` or `Service.Name`,
+        /// where `Name` is a procedure or class and `*` and `?` are wildcards
+        #[arg(long)]
+        include: Vec<String>,
+        /// Leave out procedures matching `Service` or `Service.Name`
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Compare the output with freshly generated code instead of writing
+        /// it, printing a diff and failing if they differ
+        #[arg(long)]
+        check: bool,
+        /// Formatting of the generated code: prettyplease, rustfmt or none
+        #[arg(long, default_value = "prettyplease")]
+        format: krpc_gen::Format,
+        /// Name getters `name()` instead of `get_name()`
+        #[arg(long)]
+        idiomatic_accessors: bool,
+        /// Code generator for client bindings: handlebars or tokens
+        #[arg(long, default_value = "handlebars")]
+        backend: krpc_gen::Backend,
+        /// Resolution of methods mapping to the same name: suffix, skip or error
+        #[arg(long, default_value = "suffix")]
+        collisions: krpc_gen::Collisions,
+        /// Output layout of client bindings: file, or modules to write a
+        /// directory with a file per class
+        #[arg(long, default_value = "file")]
+        layout: krpc_gen::Layout,
+        /// Treat the output as a directory with a file per service and a
+        /// `mod.rs` putting each service behind a cargo feature
+        #[arg(long)]
+        features: bool,
+        /// Write the `[features]` table for `Cargo.toml` to this file;
+        /// implies `--features`
+        #[arg(long)]
+        cargo_features: Option<PathBuf>,
+        /// Generate each method as one call to the runtime's generic
+        /// `Connection::call`
+        #[arg(long)]
+        call_helper: bool,
+        /// Generate a snapshot struct per class with `fetch_snapshot()`
+        /// reading its properties in one batch
+        #[arg(long)]
+        snapshots: bool,
+        /// Derive serde traits for the snapshot structs; implies
+        /// `--snapshots`
+        #[arg(long)]
+        snapshot_serde: bool,
+        /// Generate a method per property returning a `Property` with
+        /// `get()`, `set()` and `stream()`
+        #[arg(long)]
+        property_objects: bool,
+        /// Delete generated service files and directories in the output
+        /// directory that are no longer generated
+        #[arg(long)]
+        managed: bool,
+    },
+    /// Report the changes between two schema sets
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 fn main() {
+    let cli = Cli::parse();
 
-    krpc_gen::generate_for(
-        std::path::Path::new("/home/bart/.local/share/Steam/steamapps/common/Kerbal Space Program/GameData/kRPC/KRPC.SpaceCenter.json"),
-        std::path::Path::new("output/space_center.rs")
-    );
+    match cli.command {
+        Command::Generate { schema, output, server, procedure_ids, krpc_version, template_dir, config, include, exclude, check, format, idiomatic_accessors, backend, collisions, layout, features, cargo_features, call_helper, snapshots, snapshot_serde, property_objects, managed } => {
+            let options = krpc_gen::Options {
+                procedure_ids,
+                krpc_version,
+                template_dir,
+                config: config.or_else(|| Some(PathBuf::from("krpc-gen.toml")).filter(|path| path.is_file())),
+                include,
+                exclude,
+                format,
+                idiomatic_accessors,
+                backend,
+                collisions,
+                layout,
+                features: features || cargo_features.is_some(),
+                cargo_features,
+                call_helper,
+                snapshots: snapshots || snapshot_serde,
+                snapshot_serde,
+                property_objects,
+                managed,
+            };
+            if check {
+                let result = if server {
+                    krpc_gen::check_server_with_options(&schema, &output, &options)
+                } else {
+                    krpc_gen::check_with_options(&schema, &output, &options)
+                };
+                match result {
+                    Ok(mismatches) if mismatches.is_empty() => {},
+                    Ok(mismatches) => {
+                        for mismatch in &mismatches {
+                            print!("{}", mismatch);
+                        }
+                        eprintln!("{} is out of date", output.display());
+                        std::process::exit(1);
+                    },
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        std::process::exit(2);
+                    },
+                }
+            } else {
+                let result = if server {
+                    krpc_gen::generate_server_with_options(&schema, &output, &options)
+                } else {
+                    krpc_gen::generate_with_options(&schema, &output, &options)
+                };
+                if let Err(e) = result {
+                    eprintln!("error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        },
+        Command::Diff { old, new, json } => {
+            let diff = match krpc_gen::diff_schemas(&old, &new) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(2);
+                },
+            };
+            if json {
+                println!("{}", diff.to_json());
+            } else {
+                print!("{}", diff);
+            }
+        },
+    }
 }